@@ -0,0 +1,356 @@
+use regex::Regex;
+use reqwest::Url;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Error, ErrorKind};
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+// nginx/Apache combined format, up to the status:
+// 203.0.113.7 - - [10/Oct/2025:13:55:36 +0000] "GET /a?b=c HTTP/1.1" 200 2326 ...
+static COMBINED: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^\S+ \S+ \S+ \[[^\]]*\] "(\S+) (\S+)[^"]*" (\d{3}) "#).unwrap());
+
+/// Serve the paths logged for `host` from `base` instead, e.g.
+/// `internal-web-1=https://www.example.com`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HostMapping {
+    pub host: String,
+    pub base: Url,
+}
+
+impl FromStr for HostMapping {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Error> {
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidInput, msg);
+        let (host, base) = spec
+            .split_once('=')
+            .ok_or_else(|| invalid(format!("Host mapping {:?} is not host=url", spec)))?;
+        let base = base
+            .parse()
+            .map_err(|e| invalid(format!("{}: {}", base, e)))?;
+
+        Ok(HostMapping {
+            host: host.to_ascii_lowercase(),
+            base,
+        })
+    }
+}
+
+/// How much of the ranking to keep.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Cutoff {
+    #[default]
+    All,
+    /// The most requested URLs.
+    Top(usize),
+    /// The most requested URLs that together got this percentage of hits.
+    Coverage(f64),
+}
+
+/// What to import from an access log, and how to turn paths into URLs.
+#[derive(Clone, Debug, Default)]
+pub struct ImportOptions {
+    /// Keep only paths matching one of these, if any are given.
+    pub include: Vec<Regex>,
+    /// Drop paths matching any of these.
+    pub exclude: Vec<Regex>,
+    /// For lines that don't name a host; combined-format lines never do.
+    /// Paths are appended to it, so it may have a path of its own.
+    pub base_url: Option<Url>,
+    /// Per-host bases; other hosts are fetched over HTTPS as they are.
+    pub hosts: Vec<HostMapping>,
+    pub cutoff: Cutoff,
+}
+
+/// A URL with the number of times it was requested.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RankedUrl {
+    pub url: String,
+    pub hits: u64,
+}
+
+/// One request from the log.
+#[derive(Debug, Eq, PartialEq)]
+struct Request {
+    host: Option<String>,
+    method: String,
+    // a path, or an absolute URL when the request went to a proxy
+    target: String,
+    status: u16,
+}
+
+/// `path`, with any query, appended to `base`, keeping any path the base
+/// has.
+pub fn join(base: &Url, path: &str) -> String {
+    format!("{}{}", base.as_str().trim_end_matches('/'), path)
+}
+
+fn parse_combined(line: &str) -> Option<Request> {
+    let captures = COMBINED.captures(line)?;
+    Some(Request {
+        host: None,
+        method: captures[1].to_string(),
+        target: captures[2].to_string(),
+        status: captures[3].parse().ok()?,
+    })
+}
+
+fn parse_json(line: &str) -> Option<Request> {
+    let entry: Value = serde_json::from_str(line).ok()?;
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| entry.get(*name))
+            .and_then(|value| match value {
+                Value::String(value) => Some(value.clone()),
+                Value::Number(value) => Some(value.to_string()),
+                _ => None,
+            })
+    };
+
+    let (mut method, mut target) = (field(&["method", "request_method"]), None);
+    if let Some(request) = field(&["request"]) {
+        // "GET /a HTTP/1.1", as in the combined format
+        let mut parts = request.split_whitespace();
+        method = method.or(parts.next().map(str::to_string));
+        target = parts.next().map(str::to_string);
+    }
+
+    Some(Request {
+        host: field(&["host", "vhost", "server_name"]),
+        method: method?,
+        target: field(&["uri", "request_uri", "path"]).or(target)?,
+        status: field(&["status"])?.parse().ok()?,
+    })
+}
+
+/// Counts successful GET requests per URL in nginx/Apache combined-format
+/// or JSON (one object per line) access logs, and ranks them.
+#[derive(Debug, Default)]
+pub struct Import {
+    options: ImportOptions,
+    hits: HashMap<String, u64>,
+    pub lines: usize,
+    /// Lines that were neither combined format nor JSON.
+    pub unparsed: usize,
+    /// Requests left out: not a successful GET, filtered, or without a
+    /// host to send them to.
+    pub ignored: usize,
+}
+
+impl Import {
+    pub fn new(options: ImportOptions) -> Self {
+        Import {
+            options,
+            ..Default::default()
+        }
+    }
+
+    pub fn read<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        for line in reader.lines() {
+            self.add_line(&line?);
+        }
+
+        Ok(())
+    }
+
+    pub fn add_line(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+
+        self.lines += 1;
+        let request = if line.starts_with('{') {
+            parse_json(line)
+        } else {
+            parse_combined(line)
+        };
+        let Some(request) = request else {
+            self.unparsed += 1;
+            return;
+        };
+
+        match self.url(&request) {
+            Some(url) => *self.hits.entry(url).or_default() += 1,
+            None => self.ignored += 1,
+        }
+    }
+
+    fn url(&self, request: &Request) -> Option<String> {
+        let successful = (200..300).contains(&request.status) || request.status == 304;
+        if !request.method.eq_ignore_ascii_case("GET") || !successful {
+            return None;
+        }
+
+        let (host, path) = match Url::parse(&request.target) {
+            Ok(url) => (
+                url.host_str().map(str::to_string),
+                match url.query() {
+                    Some(query) => format!("{}?{}", url.path(), query),
+                    None => url.path().to_string(),
+                },
+            ),
+            Err(_) => (request.host.clone(), request.target.clone()),
+        };
+        if !path.starts_with('/') {
+            return None;
+        }
+
+        let options = &self.options;
+        let plain_path = path.split('?').next().unwrap_or_default();
+        if (!options.include.is_empty()
+            && !options.include.iter().any(|re| re.is_match(plain_path)))
+            || options.exclude.iter().any(|re| re.is_match(plain_path))
+        {
+            return None;
+        }
+
+        let host = host.map(|host| host.to_ascii_lowercase());
+        let base = match &host {
+            Some(host) => match options.hosts.iter().find(|mapping| mapping.host == *host) {
+                Some(mapping) => mapping.base.clone(),
+                None => Url::parse(&format!("https://{}/", host)).ok()?,
+            },
+            None => options.base_url.clone()?,
+        };
+
+        Url::parse(&join(&base, &path)).ok().map(String::from)
+    }
+
+    /// Hits of every URL counted, before the cutoff.
+    pub fn total_hits(&self) -> u64 {
+        self.hits.values().sum()
+    }
+
+    /// The URLs, most requested first, up to the cutoff.
+    pub fn ranked(&self) -> Vec<RankedUrl> {
+        let mut ranked: Vec<_> = self
+            .hits
+            .iter()
+            .map(|(url, hits)| RankedUrl {
+                url: url.clone(),
+                hits: *hits,
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.url.cmp(&b.url)));
+
+        match self.options.cutoff {
+            Cutoff::All => {}
+            Cutoff::Top(count) => ranked.truncate(count),
+            Cutoff::Coverage(pct) => {
+                let needed = pct / 100.0 * self.total_hits() as f64;
+                let mut covered = 0;
+                let count = ranked
+                    .iter()
+                    .take_while(|url| {
+                        let more = (covered as f64) < needed;
+                        covered += url.hits;
+                        more
+                    })
+                    .count();
+                ranked.truncate(count);
+            }
+        }
+
+        ranked
+    }
+}
+
+impl fmt::Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranked = self.ranked();
+        let covered: u64 = ranked.iter().map(|url| url.hits).sum();
+        let total = self.total_hits();
+        write!(
+            f,
+            "{} urls covering {} of {} hits ({:.1}%) from {} lines ({} unparsed, {} ignored)",
+            ranked.len(),
+            covered,
+            total,
+            if total == 0 {
+                0.0
+            } else {
+                covered as f64 / total as f64 * 100.0
+            },
+            self.lines,
+            self.unparsed,
+            self.ignored
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::accesslog::{Cutoff, Import, ImportOptions, RankedUrl};
+    use regex::Regex;
+
+    const LOG: &str = r#"
+203.0.113.7 - - [10/Oct/2025:13:55:36 +0000] "GET /a HTTP/1.1" 200 2326 "-" "curl/8.0"
+203.0.113.7 - - [10/Oct/2025:13:55:37 +0000] "GET /a HTTP/1.1" 304 0 "-" "curl/8.0"
+203.0.113.8 - - [10/Oct/2025:13:55:38 +0000] "GET /b?page=2 HTTP/1.1" 200 100 "-" "-"
+203.0.113.8 - - [10/Oct/2025:13:55:39 +0000] "POST /a HTTP/1.1" 200 100 "-" "-"
+203.0.113.8 - - [10/Oct/2025:13:55:40 +0000] "GET /missing HTTP/1.1" 404 100 "-" "-"
+203.0.113.8 - - [10/Oct/2025:13:55:41 +0000] "GET /static/app.js HTTP/1.1" 200 100 "-" "-"
+{"host": "internal", "request": "GET /a HTTP/1.1", "status": 200}
+{"host": "Shop.example.com", "method": "GET", "uri": "/c", "status": "200"}
+{"host": "shop.example.com", "method": "GET", "uri": "/c", "status": 200}
+not a log line
+"#;
+
+    fn import(cutoff: Cutoff) -> Import {
+        let mut import = Import::new(ImportOptions {
+            exclude: vec![Regex::new(r"^/static/").unwrap()],
+            base_url: Some("https://www.example.com".parse().unwrap()),
+            hosts: vec!["internal=https://www.example.com".parse().unwrap()],
+            cutoff,
+            ..Default::default()
+        });
+        import.read(LOG.as_bytes()).unwrap();
+        import
+    }
+
+    fn ranked(import: &Import) -> Vec<(String, u64)> {
+        import
+            .ranked()
+            .into_iter()
+            .map(|RankedUrl { url, hits }| (url, hits))
+            .collect()
+    }
+
+    fn urls(urls: &[(&str, u64)]) -> Vec<(String, u64)> {
+        urls.iter()
+            .map(|(url, hits)| (url.to_string(), *hits))
+            .collect()
+    }
+
+    #[test]
+    fn test_import() {
+        let all = import(Cutoff::All);
+        assert_eq!(
+            ranked(&all),
+            urls(&[
+                ("https://www.example.com/a", 3),
+                ("https://shop.example.com/c", 2),
+                ("https://www.example.com/b?page=2", 1),
+            ])
+        );
+        assert_eq!((all.lines, all.unparsed, all.ignored), (10, 1, 3));
+
+        assert_eq!(import(Cutoff::Top(1)).ranked().len(), 1);
+        // the first two cover 5 of 6 hits
+        assert_eq!(import(Cutoff::Coverage(80.0)).ranked().len(), 2);
+        assert_eq!(import(Cutoff::Coverage(100.0)).ranked().len(), 3);
+
+        let mut include = Import::new(ImportOptions {
+            include: vec![Regex::new(r"^/[ab]$").unwrap()],
+            ..Default::default()
+        });
+        include.read(LOG.as_bytes()).unwrap();
+        // combined-format lines have no host, and there's no base to use
+        assert_eq!(ranked(&include), urls(&[("https://internal/a", 1)]));
+    }
+}
@@ -0,0 +1,304 @@
+use crate::breaker::host_key;
+use crate::fetch::{FetchError, Fetched};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// weights of the newest latency in the short and the long term average
+const SHORT_WEIGHT: f64 = 0.5;
+const LONG_WEIGHT: f64 = 0.05;
+
+// how many points of the history the report shows per host
+const REPORT_POINTS: usize = 12;
+
+/// How the number of requests in flight to each host adapts: up by one
+/// while latency stays stable, and cut by `backoff` when it rises or a
+/// request fails (AIMD).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveConfig {
+    pub initial: usize,
+    pub min: usize,
+    pub max: usize,
+    /// Latency counts as risen once its short-term average is this many
+    /// times the long-term one.
+    pub tolerance: f64,
+    /// What the limit is multiplied by when cutting back.
+    pub backoff: f64,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        AdaptiveConfig {
+            initial: 4,
+            min: 1,
+            max: 256,
+            tolerance: 2.0,
+            backoff: 0.5,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HostState {
+    limit: f64,
+    // permits the semaphore has, counting those in use
+    permits: usize,
+    in_flight: usize,
+    // latency averages, in seconds
+    short: Option<f64>,
+    long: Option<f64>,
+    last_cut: Option<Instant>,
+    // the whole-number limit whenever it changed, since the run started
+    history: Vec<(Duration, usize)>,
+}
+
+#[derive(Debug)]
+struct Host {
+    config: AdaptiveConfig,
+    run_start: Instant,
+    semaphore: Arc<Semaphore>,
+    state: Mutex<HostState>,
+}
+
+impl Host {
+    fn new(config: AdaptiveConfig, run_start: Instant) -> Self {
+        let initial = config.initial.clamp(config.min.max(1), config.max);
+        Host {
+            config,
+            run_start,
+            semaphore: Arc::new(Semaphore::new(initial)),
+            state: Mutex::new(HostState {
+                limit: initial as f64,
+                permits: initial,
+                in_flight: 0,
+                short: None,
+                long: None,
+                last_cut: None,
+                history: vec![(run_start.elapsed(), initial)],
+            }),
+        }
+    }
+
+    fn adjust(&self, state: &mut HostState, sent: Instant, failed: bool, latency: Duration) {
+        let config = &self.config;
+        let mut risen = false;
+        if !failed {
+            let latency = latency.as_secs_f64();
+            let average = |average: Option<f64>, weight| match average {
+                Some(average) => average + (latency - average) * weight,
+                None => latency,
+            };
+            let (short, long) = (
+                average(state.short, SHORT_WEIGHT),
+                average(state.long, LONG_WEIGHT),
+            );
+            (state.short, state.long) = (Some(short), Some(long));
+            risen = short > long * config.tolerance;
+        }
+
+        if failed || risen {
+            // requests sent before the last cut don't know about it yet
+            if state.last_cut.is_none_or(|cut| sent > cut) {
+                state.limit = (state.limit * config.backoff).max(config.min.max(1) as f64);
+                state.last_cut = Some(Instant::now());
+            }
+        } else if state.in_flight as f64 * 2.0 >= state.limit {
+            // only raise a limit that is actually being used
+            state.limit = (state.limit + 1.0).min(config.max as f64);
+        }
+    }
+}
+
+/// The right to send one request to a host; dropping it without
+/// [`Slot::release`] leaves the limit as it was.
+#[derive(Debug)]
+pub struct Slot {
+    host: Arc<Host>,
+    permit: Option<OwnedSemaphorePermit>,
+    sent: Instant,
+}
+
+impl Slot {
+    /// Give the slot back with the result of the request sent in it.
+    pub fn release(self, result: &Result<Fetched, FetchError>) {
+        let (failed, latency) = match result {
+            Ok(fetched) => (fetched.status.is_server_error(), fetched.stats.elapsed_time),
+            Err(_) => (true, self.sent.elapsed()),
+        };
+        let mut state = self.host.state.lock().unwrap();
+        self.host.adjust(&mut state, self.sent, failed, latency);
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        let mut state = self.host.state.lock().unwrap();
+        state.in_flight -= 1;
+
+        let target = state.limit as usize;
+        if target > state.permits {
+            self.host.semaphore.add_permits(target - state.permits);
+            state.permits = target;
+        }
+        // shrink one returned permit at a time
+        if state.permits > target {
+            if let Some(permit) = self.permit.take() {
+                permit.forget();
+            }
+            state.permits -= 1;
+        }
+
+        if state.history.last().map(|(_, limit)| *limit) != Some(state.permits) {
+            let point = (self.host.run_start.elapsed(), state.permits);
+            state.history.push(point);
+        }
+    }
+}
+
+/// An adaptive concurrency limit per host.
+#[derive(Debug)]
+pub struct Limits {
+    config: AdaptiveConfig,
+    start: Instant,
+    hosts: Mutex<HashMap<String, Arc<Host>>>,
+}
+
+impl Limits {
+    pub fn new(config: AdaptiveConfig) -> Self {
+        Limits {
+            config,
+            start: Instant::now(),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Wait until another request to the host of `url` may be sent.
+    pub async fn acquire(&self, url: &str) -> Slot {
+        let host = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host_key(url))
+            .or_insert_with(|| Arc::new(Host::new(self.config, self.start)))
+            .clone();
+
+        // the semaphore is never closed
+        let permit = host.semaphore.clone().acquire_owned().await.unwrap();
+        host.state.lock().unwrap().in_flight += 1;
+        Slot {
+            host,
+            permit: Some(permit),
+            sent: Instant::now(),
+        }
+    }
+
+    pub fn report(&self) -> ConcurrencyReport {
+        let hosts = self.hosts.lock().unwrap();
+        ConcurrencyReport {
+            hosts: hosts
+                .iter()
+                .map(|(key, host)| (key.clone(), host.state.lock().unwrap().history.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// How the concurrency limit of each host changed over a run.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConcurrencyReport {
+    /// The limit whenever it changed, by time since the run started.
+    pub hosts: BTreeMap<String, Vec<(Duration, usize)>>,
+}
+
+impl ConcurrencyReport {
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+impl fmt::Display for ConcurrencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "adaptive concurrency:")?;
+        for (host, history) in &self.hosts {
+            let limits = history.iter().map(|(_, limit)| *limit);
+            writeln!(
+                f,
+                "  {:<30} final {:>4}  min {:>4}  max {:>4}",
+                host,
+                limits.clone().next_back().unwrap_or_default(),
+                limits.clone().min().unwrap_or_default(),
+                limits.max().unwrap_or_default()
+            )?;
+
+            // evenly spread points, always including the last one
+            let step = history.len().div_ceil(REPORT_POINTS).max(1);
+            let points: Vec<_> = history
+                .iter()
+                .enumerate()
+                .filter(|(n, _)| n % step == 0 || *n == history.len() - 1)
+                .map(|(_, (at, limit))| format!("{:.1}s {}", at.as_secs_f64(), limit))
+                .collect();
+            writeln!(f, "    {}", points.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adaptive::{AdaptiveConfig, Limits};
+    use crate::fetch::Fetched;
+    use std::time::Duration;
+
+    const URL: &str = "https://example.com/";
+
+    fn history(limits: &Limits) -> Vec<usize> {
+        limits.report().hosts["example.com"]
+            .iter()
+            .map(|(_, limit)| *limit)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_aimd() {
+        let limits = Limits::new(AdaptiveConfig {
+            initial: 2,
+            max: 4,
+            ..AdaptiveConfig::default()
+        });
+
+        // stable latency with both slots busy raises the limit up to the max
+        for _ in 0..3 {
+            let (a, b) = (limits.acquire(URL).await, limits.acquire(URL).await);
+            a.release(&Ok(Fetched::with_status(200).took(10)));
+            b.release(&Ok(Fetched::with_status(200).took(10)));
+        }
+        assert_eq!(history(&limits), [2, 3, 4]);
+
+        // failures of requests sent before the cut only cut once
+        let slots = [
+            limits.acquire(URL).await,
+            limits.acquire(URL).await,
+            limits.acquire(URL).await,
+        ];
+        for slot in slots {
+            slot.release(&Err("reset".into()));
+        }
+        assert_eq!(history(&limits), [2, 3, 4, 3, 2]);
+
+        // and so does a latency spike
+        let slot = limits.acquire(URL).await;
+        slot.release(&Ok(Fetched::with_status(200).took(100)));
+        assert_eq!(*history(&limits).last().unwrap(), 1);
+
+        // at the limit, the next request has to wait for a slot
+        let slot = limits.acquire(URL).await;
+        let waiting = tokio::time::timeout(Duration::from_millis(20), limits.acquire(URL));
+        assert!(waiting.await.is_err());
+        drop(slot);
+        limits.acquire(URL).await;
+    }
+}
@@ -0,0 +1,213 @@
+use regex::Regex;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+
+/// A check run against every response of a URL.
+///
+/// Assertions follow the URL on its line in the URL list, as
+/// whitespace-separated `key=value` pairs:
+///
+/// ```text
+/// https://example.com/ status=200 max-latency=500ms min-size=1000 header=ETag contains=<html>
+/// ```
+#[derive(Clone, Debug)]
+pub enum Assertion {
+    Status(StatusCode),
+    MaxLatency(Duration),
+    MinSize(usize),
+    MaxSize(usize),
+    Header(String, Option<String>),
+    Contains(String),
+    Matches(Regex),
+    Sha256(String),
+}
+
+/// What a single request observed, as far as the assertions are concerned.
+pub struct Observed<'a> {
+    pub status: StatusCode,
+    pub latency: Duration,
+    pub headers: &'a HeaderMap,
+    pub body: &'a [u8],
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+fn parse_duration(value: &str) -> Result<Duration, Error> {
+    let (number, unit) = match value.strip_suffix("ms") {
+        Some(number) => (number, Duration::from_millis(1)),
+        None => match value.strip_suffix('s') {
+            Some(number) => (number, Duration::from_secs(1)),
+            None => (value, Duration::from_millis(1)),
+        },
+    };
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| invalid(format!("Invalid duration {:?}", value)))?;
+
+    Ok(unit.mul_f64(number))
+}
+
+fn parse_size(value: &str) -> Result<usize, Error> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("Invalid size {:?}", value)))
+}
+
+impl Assertion {
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let (key, value) = spec
+            .split_once('=')
+            .ok_or_else(|| invalid(format!("Assertion {:?} is not key=value", spec)))?;
+
+        Ok(match key {
+            "status" => Assertion::Status(
+                value
+                    .parse()
+                    .map_err(|_| invalid(format!("Invalid status {:?}", value)))?,
+            ),
+            "max-latency" => Assertion::MaxLatency(parse_duration(value)?),
+            "min-size" => Assertion::MinSize(parse_size(value)?),
+            "max-size" => Assertion::MaxSize(parse_size(value)?),
+            "header" => match value.split_once(':') {
+                Some((name, expected)) => {
+                    Assertion::Header(name.to_string(), Some(expected.to_string()))
+                }
+                None => Assertion::Header(value.to_string(), None),
+            },
+            "contains" => Assertion::Contains(value.to_string()),
+            "matches" => Assertion::Matches(
+                Regex::new(value).map_err(|e| invalid(format!("Invalid regex: {}", e)))?,
+            ),
+            "sha256" => Assertion::Sha256(value.to_ascii_lowercase()),
+            _ => return Err(invalid(format!("Unknown assertion {:?}", key))),
+        })
+    }
+
+    /// Returns a description of the failure, if the assertion does not hold.
+    pub fn check(&self, observed: &Observed) -> Option<String> {
+        match self {
+            Assertion::Status(expected) if observed.status != *expected => Some(format!(
+                "status {}, expected {}",
+                observed.status.as_u16(),
+                expected.as_u16()
+            )),
+            Assertion::MaxLatency(max) if observed.latency > *max => Some(format!(
+                "latency {:?}, expected at most {:?}",
+                observed.latency, max
+            )),
+            Assertion::MinSize(min) if observed.body.len() < *min => Some(format!(
+                "body size {}, expected at least {}",
+                observed.body.len(),
+                min
+            )),
+            Assertion::MaxSize(max) if observed.body.len() > *max => Some(format!(
+                "body size {}, expected at most {}",
+                observed.body.len(),
+                max
+            )),
+            Assertion::Header(name, expected) => match (observed.headers.get(name), expected) {
+                (None, _) => Some(format!("header {} missing", name)),
+                (Some(value), Some(expected)) if value.as_bytes() != expected.as_bytes() => {
+                    Some(format!(
+                        "header {} is {:?}, expected {:?}",
+                        name,
+                        String::from_utf8_lossy(value.as_bytes()),
+                        expected
+                    ))
+                }
+                _ => None,
+            },
+            Assertion::Contains(needle) => {
+                let body = String::from_utf8_lossy(observed.body);
+                if body.contains(needle.as_str()) {
+                    None
+                } else {
+                    Some(format!("body does not contain {:?}", needle))
+                }
+            }
+            Assertion::Matches(regex) => {
+                let body = String::from_utf8_lossy(observed.body);
+                if regex.is_match(&body) {
+                    None
+                } else {
+                    Some(format!("body does not match /{}/", regex))
+                }
+            }
+            Assertion::Sha256(expected) => {
+                let actual = format!("{:x}", Sha256::digest(observed.body));
+                if actual == *expected {
+                    None
+                } else {
+                    Some(format!("body sha256 {}, expected {}", actual, expected))
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Split a URL list line into the URL and its assertions.
+pub fn parse_line(line: &str) -> Result<(String, Vec<Assertion>), Error> {
+    let mut parts = line.split_whitespace();
+    let url = parts.next().unwrap_or_default().to_string();
+    let assertions = parts.map(Assertion::parse).collect::<Result<_, _>>()?;
+
+    Ok((url, assertions))
+}
+
+pub fn check_all(assertions: &[Assertion], observed: &Observed) -> Vec<String> {
+    assertions
+        .iter()
+        .filter_map(|assertion| assertion.check(observed))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::assertions::{Observed, check_all, parse_line};
+    use reqwest::StatusCode;
+    use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderValue};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_line() {
+        let (url, assertions) =
+            parse_line("https://example.com/ status=200 max-latency=1.5s header=ETag").unwrap();
+
+        assert_eq!(url, "https://example.com/");
+        assert_eq!(assertions.len(), 3);
+        assert!(parse_line("https://example.com/ status=abc").is_err());
+        assert!(parse_line("https://example.com/ bogus=1").is_err());
+    }
+
+    #[test]
+    fn test_check_all() {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/html"));
+        let observed = Observed {
+            status: StatusCode::OK,
+            latency: Duration::from_millis(700),
+            headers: &headers,
+            body: b"hello world",
+        };
+
+        let (_, passing) = parse_line(
+            "u status=200 max-latency=1s min-size=5 max-size=20 header=Content-Type:text/html \
+             contains=world matches=^hel+o \
+             sha256=b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        )
+        .unwrap();
+        assert_eq!(check_all(&passing, &observed), Vec::<String>::new());
+
+        let (_, failing) =
+            parse_line("u status=404 max-latency=500ms min-size=100 header=ETag contains=bye")
+                .unwrap();
+        assert_eq!(check_all(&failing, &observed).len(), 5);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::breaker::host_key;
+use reqwest::cookie::Jar;
+use reqwest::{RequestBuilder, Url};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// refresh OAuth2 tokens a bit before they actually expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// Credentials attached to the requests to the hosts they are for.
+///
+/// These come from a JSON credentials file or from `CACHEWARMER_*`
+/// environment variables, never from the command line, so that secrets
+/// don't end up in shell history or the process list.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Credentials {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer {
+        token: String,
+    },
+    OAuth2 {
+        token_url: String,
+        client_id: String,
+        client_secret: String,
+        scope: Option<String>,
+    },
+}
+
+impl Credentials {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Read credentials from the environment, if any are set.
+    pub fn from_env() -> Result<Option<Self>, Error> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Result<Option<Self>, Error> {
+        if let Some(path) = var("CACHEWARMER_CREDENTIALS_FILE") {
+            return Self::from_file(path).map(Some);
+        }
+
+        if let Some(token) = var("CACHEWARMER_BEARER_TOKEN") {
+            return Ok(Some(Credentials::Bearer { token }));
+        }
+
+        if let Some(username) = var("CACHEWARMER_BASIC_USERNAME") {
+            return Ok(Some(Credentials::Basic {
+                username,
+                password: var("CACHEWARMER_BASIC_PASSWORD"),
+            }));
+        }
+
+        if let Some(token_url) = var("CACHEWARMER_OAUTH2_TOKEN_URL") {
+            let required = |name: &str| {
+                var(name).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, format!("{} is not set", name))
+                })
+            };
+
+            return Ok(Some(Credentials::OAuth2 {
+                token_url,
+                client_id: required("CACHEWARMER_OAUTH2_CLIENT_ID")?,
+                client_secret: required("CACHEWARMER_OAUTH2_CLIENT_SECRET")?,
+                scope: var("CACHEWARMER_OAUTH2_SCOPE"),
+            }));
+        }
+
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Token {
+    access_token: String,
+    requested_at: Instant,
+    expires_at: Option<Instant>,
+}
+
+impl Token {
+    fn is_fresh(&self) -> bool {
+        match self.expires_at {
+            Some(expires_at) => Instant::now() + EXPIRY_MARGIN < expires_at,
+            None => true,
+        }
+    }
+}
+
+/// Applies credentials to requests to the given hosts, sharing one OAuth2
+/// token between all concurrent requests.
+///
+/// When the token is about to expire, the first request to notice fetches
+/// a new one while the others wait for it, so the token endpoint sees a
+/// single request per refresh.
+#[derive(Debug)]
+pub struct Auth {
+    credentials: Credentials,
+    hosts: Vec<String>,
+    token: tokio::sync::Mutex<Option<Token>>,
+}
+
+impl Auth {
+    /// Credentials for `hosts`, each a host name, or `host:port` to only
+    /// send them to that port.
+    pub fn new(credentials: Credentials, hosts: Vec<String>) -> Self {
+        Auth {
+            credentials,
+            hosts: hosts.iter().map(|host| host.to_ascii_lowercase()).collect(),
+            token: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Whether requests to `url` get the credentials, so that they aren't
+    /// handed to every host in the list or redirected to.
+    pub fn applies_to(&self, url: &Url) -> bool {
+        let key = host_key(url.as_str());
+        let host = url.host_str().unwrap_or_default();
+        self.hosts
+            .iter()
+            .any(|allowed| *allowed == key || allowed == host)
+    }
+
+    /// Whether a rejected request is worth retrying with fresh credentials.
+    pub fn refreshes(&self) -> bool {
+        matches!(self.credentials, Credentials::OAuth2 { .. })
+    }
+
+    pub async fn apply(
+        &self,
+        client: &reqwest::Client,
+        request: RequestBuilder,
+    ) -> Result<RequestBuilder, reqwest::Error> {
+        Ok(match &self.credentials {
+            Credentials::Basic { username, password } => {
+                request.basic_auth(username, password.as_ref())
+            }
+            Credentials::Bearer { token } => request.bearer_auth(token),
+            Credentials::OAuth2 { .. } => request.bearer_auth(self.access_token(client).await?),
+        })
+    }
+
+    /// Forget the OAuth2 token after the origin rejected it at
+    /// `rejected_at`. A token requested since then, by another request
+    /// that was rejected too, is kept, so they share a single refresh.
+    pub async fn invalidate(&self, rejected_at: Instant) {
+        let mut token = self.token.lock().await;
+        if token
+            .as_ref()
+            .is_some_and(|token| token.requested_at < rejected_at)
+        {
+            *token = None;
+        }
+    }
+
+    async fn access_token(&self, client: &reqwest::Client) -> Result<String, reqwest::Error> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|token| token.is_fresh()) {
+            return Ok(token.access_token.clone());
+        }
+
+        let fresh = self.fetch_token(client).await?;
+        let access_token = fresh.access_token.clone();
+        *token = Some(fresh);
+
+        Ok(access_token)
+    }
+
+    async fn fetch_token(&self, client: &reqwest::Client) -> Result<Token, reqwest::Error> {
+        let Credentials::OAuth2 {
+            token_url,
+            client_id,
+            client_secret,
+            scope,
+        } = &self.credentials
+        else {
+            unreachable!("only OAuth2 credentials use tokens");
+        };
+
+        let mut form = vec![("grant_type", "client_credentials")];
+        if let Some(scope) = scope {
+            form.push(("scope", scope));
+        }
+
+        let requested_at = Instant::now();
+        let resp: TokenResponse = client
+            .post(token_url)
+            .basic_auth(client_id, Some(client_secret))
+            .form(&form)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(Token {
+            access_token: resp.access_token,
+            requested_at,
+            expires_at: resp
+                .expires_in
+                .map(|secs| requested_at + Duration::from_secs(secs)),
+        })
+    }
+}
+
+/// Load a cookie jar from a file of `<url> <Set-Cookie header value>` lines.
+///
+/// Cookies set by the warmed sites themselves are kept in the same jar,
+/// so session cookies carry over between requests.
+pub fn load_cookie_jar<P: AsRef<Path>>(path: P) -> Result<Arc<Jar>, Error> {
+    let jar = Jar::default();
+    let file = BufReader::new(File::open(path)?);
+
+    for line in file.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (url, cookie) = line.split_once(char::is_whitespace).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid cookie line {:?}", line),
+            )
+        })?;
+        let url: Url = url
+            .parse()
+            .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}: {}", url, e)))?;
+
+        jar.add_cookie_str(cookie.trim(), &url);
+    }
+
+    Ok(Arc::new(jar))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::{Auth, Credentials};
+    use reqwest::Url;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_auth_hosts() {
+        let bearer = Credentials::Bearer {
+            token: "t".to_string(),
+        };
+        let hosts = vec![
+            "Example.com".to_string(),
+            "api.example.com:8443".to_string(),
+        ];
+        let auth = Auth::new(bearer, hosts);
+        let applies = |url: &str| auth.applies_to(&Url::parse(url).unwrap());
+
+        assert!(applies("https://example.com/a"));
+        assert!(applies("http://example.com:8080/a"));
+        assert!(applies("https://api.example.com:8443/a"));
+        assert!(!applies("https://api.example.com/a"));
+        assert!(!applies("https://cdn.example.com/a"));
+        assert!(!auth.refreshes());
+    }
+
+    #[test]
+    fn test_credentials_from_vars() {
+        let vars: HashMap<_, _> = [
+            ("CACHEWARMER_BASIC_USERNAME", "user"),
+            ("CACHEWARMER_BASIC_PASSWORD", "pass"),
+        ]
+        .into_iter()
+        .collect();
+        let credentials = Credentials::from_vars(|name| vars.get(name).map(|v| v.to_string()));
+
+        assert_eq!(
+            credentials.unwrap(),
+            Some(Credentials::Basic {
+                username: "user".to_string(),
+                password: Some("pass".to_string()),
+            })
+        );
+
+        let vars: HashMap<_, _> = [("CACHEWARMER_OAUTH2_TOKEN_URL", "http://localhost/")]
+            .into_iter()
+            .collect();
+        assert!(Credentials::from_vars(|name| vars.get(name).map(|v| v.to_string())).is_err());
+    }
+}
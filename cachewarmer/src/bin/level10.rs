@@ -1,3 +1,5 @@
+use cachewarmer::progress::{Progress, Reporter};
+use cachewarmer::signals::{GRACE_PERIOD, Signals};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Error, ErrorKind};
 use std::time::{Duration, Instant};
@@ -61,10 +63,52 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let start = Instant::now();
     let mut totals = Stats::new();
     let client = reqwest::Client::new();
-    for url in url_file.lines() {
-        let url = url?;
-        let stats = get(&client, &url).await?;
-        totals.aggregate(&stats);
+    let urls: Vec<_> = url_file.lines().collect::<Result<_, _>>()?;
+    let progress = Progress::new();
+    progress.set_total(urls.len());
+    let reporter = Reporter::spawn(progress.clone());
+    let signals = Signals::install()?;
+
+    for url in urls {
+        if signals.stop_requested() {
+            break;
+        }
+
+        if signals.take_dump_request() {
+            println!(
+                "partial {:?} ({:.2} bytes/sec)",
+                totals,
+                totals.bytes_per_sec().unwrap_or_default()
+            );
+        }
+
+        progress.started();
+        let result = tokio::select! {
+            result = get(&client, &url) => result,
+            _ = async {
+                signals.stopped().await;
+                tokio::time::sleep(GRACE_PERIOD).await;
+            } => break,
+        };
+
+        match result {
+            Ok(stats) => {
+                progress.completed(stats.elapsed_time);
+                totals.aggregate(&stats);
+            }
+            Err(e) => {
+                progress.failed();
+                eprintln!("{} failed: {}", url, e);
+            }
+        }
+    }
+    reporter.finish();
+
+    if signals.stop_requested() {
+        println!(
+            "interrupted, abandoned {} requests in flight",
+            progress.snapshot().in_flight
+        );
     }
 
     println!(
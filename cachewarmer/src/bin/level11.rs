@@ -1,85 +1,72 @@
-use futures::stream::StreamExt;
-use std::fs::File;
-use std::io::{BufRead, BufReader, Error, ErrorKind};
-use std::time::{Duration, Instant};
-
-#[derive(Debug)]
-struct Stats {
-    elapsed_time: Duration,
-    content_length: usize,
-}
-
-impl Stats {
-    fn new() -> Self {
-        Stats {
-            elapsed_time: Duration::default(),
-            content_length: 0,
-        }
-    }
-
-    fn aggregate(&mut self, other: &Stats) {
-        self.elapsed_time += other.elapsed_time;
-        self.content_length += other.content_length;
-    }
-
-    fn bytes_per_sec(&self) -> Option<f64> {
-        let elapsed_sec = self.elapsed_time.as_secs_f64();
-        if elapsed_sec < 0.001 {
-            return None;
-        }
-
-        let bytes = self.content_length as f64;
-
-        Some(bytes / elapsed_sec)
-    }
-}
-
-async fn get(
-    client: &reqwest::Client,
-    url: String,
-) -> Result<Stats, Box<dyn std::error::Error>> {
-    let start = Instant::now();
-    let resp = client.get(&url).send().await?;
-
-    // can't rely on .content_length()
-    let body = resp.text().await?;
-    let elapsed_time = start.elapsed();
-
-    Ok(Stats {
-        elapsed_time,
-        content_length: body.len(),
-    })
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+// Checkpoints, JUnit/TAP reports, auth, proxies and TLS options are in
+// `cachewarmer --strategy futures-unordered`, which runs the same scheduling
+use cachewarmer::checkpoint::Checkpoint;
+use cachewarmer::fetch::Fetcher;
+use cachewarmer::input::{JobList, Source};
+use cachewarmer::progress::{Progress, Reporter};
+use cachewarmer::proxy::{PoolMode, ProxyPool};
+use cachewarmer::run::Run;
+use cachewarmer::signals::Signals;
+use cachewarmer::sink::ConsoleSink;
+use cachewarmer::strategy::{Executor, Strategy};
+use cachewarmer::timing;
+use cachewarmer::tls::{self, CertReport, TlsConfig};
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let url_path = std::env::args().nth(1);
     let url_path = url_path.ok_or(Error::new(ErrorKind::NotFound, "File name missing"))?;
 
     println!("Loading urls from {}", url_path);
 
-    let url_file = BufReader::new(File::open(url_path)?);
-    let start = Instant::now();
-    let mut totals = Stats::new();
-    let client = reqwest::Client::new();
-    let mut requests = futures::stream::FuturesUnordered::new();
-
-    for url in url_file.lines() {
-        let url = url?;
-        requests.push(get(&client, url));
-    }
+    let list = JobList::read(Source::File(url_path.into()).open()?, None)?;
+    let total = list.len() as usize;
 
-    while let Some(stats) = requests.next().await {
-        totals.aggregate(&stats?);
+    let start = Instant::now();
+    let runtime = tokio::runtime::Runtime::new()?;
+    let signals = Signals::install()?;
+    let executor = Executor::new(runtime.handle().clone(), signals.clone(), 1);
+
+    let tls = TlsConfig::default().client_config(timing::crypto_provider())?;
+    let pool = ProxyPool::new(&[], PoolMode::Every, || {
+        // the fetcher follows redirects itself, to see every hop
+        tls::configure(timing::timed(reqwest::Client::builder()), &tls)
+            .redirect(reqwest::redirect::Policy::none())
+    })?;
+    let progress = Progress::new();
+    progress.set_total(total);
+    let certs = Arc::new(Mutex::new(CertReport::default()));
+    let fetcher = Arc::new(Fetcher::new(pool, None, certs, progress.clone()));
+    let mut run = Run::new(Checkpoint::new(total), None);
+    run.add_sink(Box::new(ConsoleSink::new(false)));
+    let run = Arc::new(Mutex::new(run));
+
+    let reporter = Reporter::spawn(progress.clone());
+    executor.execute(
+        Strategy::FuturesUnordered,
+        fetcher,
+        list.jobs(),
+        run.clone(),
+    )?;
+    reporter.finish();
+
+    if signals.stop_requested() {
+        println!(
+            "interrupted, abandoned {} requests in flight",
+            progress.snapshot().in_flight
+        );
     }
 
-    println!(
-        "total {:?} ({:.2} bytes/sec)",
-        totals,
-        totals.bytes_per_sec().unwrap_or_default()
-    );
-
+    let mut run = run.lock().unwrap();
+    run.finish()?;
     println!("wall clock time: {:?}", start.elapsed());
 
+    let failed = run.failed();
+    if failed > 0 {
+        return Err(Error::other(format!("{} of {} urls failed", failed, run.cases.len())).into());
+    }
+
     Ok(())
 }
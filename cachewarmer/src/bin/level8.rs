@@ -1,3 +1,5 @@
+use cachewarmer::progress::{Progress, Reporter};
+use cachewarmer::signals::{GRACE_PERIOD, POLL_INTERVAL, Signals};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Error, ErrorKind};
 use std::sync::{Arc, Mutex};
@@ -57,20 +59,67 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let url_file = BufReader::new(File::open(url_path)?);
     let start = Instant::now();
     let totals = Arc::new(Mutex::new(Stats::new()));
+    let progress = Progress::new();
+    let reporter = Reporter::spawn(progress.clone());
+    let signals = Signals::install()?;
     let mut threads = Vec::new();
     for url in url_file.lines() {
+        if signals.stop_requested() {
+            break;
+        }
+
         let url = url?;
         let totals = totals.clone();
+        let progress = progress.clone();
         threads.push(std::thread::spawn(move || {
             let client = reqwest::blocking::Client::new();
-            let stats = get(&client, &url).unwrap();
-            totals.lock().unwrap().aggregate(&stats);
+            progress.started();
+            match get(&client, &url) {
+                Ok(stats) => {
+                    progress.completed(stats.elapsed_time);
+                    totals.lock().unwrap().aggregate(&stats);
+                }
+                Err(e) => {
+                    progress.failed();
+                    eprintln!("{} failed: {}", url, e);
+                }
+            }
         }));
     }
+    progress.set_total(threads.len());
+
+    let mut deadline = None;
+    while !threads.iter().all(|thread| thread.is_finished()) {
+        if signals.take_dump_request() {
+            let totals = totals.lock().unwrap();
+            println!(
+                "partial {:?} ({:.2} bytes/sec)",
+                totals,
+                totals.bytes_per_sec().unwrap_or_default()
+            );
+        }
+
+        if signals.stop_requested() {
+            let deadline = *deadline.get_or_insert_with(|| Instant::now() + GRACE_PERIOD);
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
 
-    for thread in threads.into_iter() {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+
+    for thread in threads.into_iter().filter(|thread| thread.is_finished()) {
         thread.join().unwrap();
     }
+    reporter.finish();
+
+    if signals.stop_requested() {
+        println!(
+            "interrupted, abandoned {} requests in flight",
+            progress.snapshot().in_flight
+        );
+    }
 
     let totals = totals.lock().unwrap();
     println!(
@@ -1,5 +1,8 @@
+use cachewarmer::progress::{Progress, Reporter};
+use cachewarmer::signals::{GRACE_PERIOD, POLL_INTERVAL, Signals};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Error, ErrorKind};
+use std::sync::mpsc::RecvTimeoutError;
 use std::time::{Duration, Instant};
 
 #[derive(Debug)]
@@ -57,29 +60,75 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let start = Instant::now();
     let mut totals = Stats::new();
     let mut threads = Vec::new();
+    let progress = Progress::new();
+    let reporter = Reporter::spawn(progress.clone());
+    let signals = Signals::install()?;
 
     let receiver = {
         let (sender, receiver) = std::sync::mpsc::channel();
         for url in url_file.lines() {
+            if signals.stop_requested() {
+                break;
+            }
+
             let url = url?;
             let sender = sender.clone();
+            let progress = progress.clone();
             threads.push(std::thread::spawn(move || {
                 let client = reqwest::blocking::Client::new();
-                let stats = get(&client, &url).unwrap();
-                sender.send(stats).unwrap();
+                progress.started();
+                match get(&client, &url) {
+                    Ok(stats) => sender.send(stats).unwrap(),
+                    Err(e) => {
+                        progress.failed();
+                        eprintln!("{} failed: {}", url, e);
+                    }
+                }
             }));
         }
+        progress.set_total(threads.len());
 
         receiver
     };
 
-    while let Ok(stats) = receiver.recv() {
-        totals.aggregate(&stats);
+    let mut deadline = None;
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(stats) => {
+                progress.completed(stats.elapsed_time);
+                totals.aggregate(&stats);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+
+        if signals.take_dump_request() {
+            println!(
+                "partial {:?} ({:.2} bytes/sec)",
+                totals,
+                totals.bytes_per_sec().unwrap_or_default()
+            );
+        }
+
+        if signals.stop_requested() {
+            let deadline = *deadline.get_or_insert_with(|| Instant::now() + GRACE_PERIOD);
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
     }
 
-    for thread in threads.into_iter() {
+    for thread in threads.into_iter().filter(|thread| thread.is_finished()) {
         thread.join().unwrap();
     }
+    reporter.finish();
+
+    if signals.stop_requested() {
+        println!(
+            "interrupted, abandoned {} requests in flight",
+            progress.snapshot().in_flight
+        );
+    }
 
     println!(
         "total {:?} ({:.2} bytes/sec)",
@@ -0,0 +1,387 @@
+use crate::fetch::{FetchError, Fetched};
+use reqwest::Url;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// When to stop sending requests to a host that is in trouble.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BreakerConfig {
+    /// Open once at least this fraction (0 to 1) of the recent requests
+    /// failed: errors, timeouts and 5xx responses.
+    pub error_rate: f64,
+    /// Requests slower than this count as failed too.
+    pub max_latency: Option<Duration>,
+    /// How many of the most recent requests to judge by; the breaker
+    /// doesn't open before this many were made.
+    pub window: usize,
+    /// How long to stay open before letting a probe request through.
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            error_rate: 0.5,
+            max_latency: None,
+            window: 20,
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The error for requests skipped while a host's breaker is open.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CircuitOpen {
+    pub host: String,
+}
+
+impl fmt::Display for CircuitOpen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "circuit breaker open for {}", self.host)
+    }
+}
+
+impl std::error::Error for CircuitOpen {}
+
+type Hosts = Arc<Mutex<HashMap<String, Host>>>;
+
+/// Permission to send one request, to hand back with its result.
+///
+/// A probe dropped without a result, e.g. when the run is cut short,
+/// lets the next request probe instead.
+#[derive(Debug)]
+pub struct Permit {
+    hosts: Hosts,
+    host: String,
+    // the request decides whether a half-open breaker closes again
+    probe: bool,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if !self.probe {
+            return;
+        }
+
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(host) = hosts.get_mut(&self.host)
+            && host.state == State::HalfOpen
+        {
+            host.state = State::Open {
+                until: Instant::now(),
+            };
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Closed,
+    Open { until: Instant },
+    // the probe is in flight
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Host {
+    state: State,
+    // whether each of the recent requests failed, oldest first
+    recent: VecDeque<bool>,
+    trips: usize,
+    skipped: usize,
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Host {
+            state: State::Closed,
+            recent: VecDeque::new(),
+            trips: 0,
+            skipped: 0,
+        }
+    }
+}
+
+/// What per-host limits are kept by: the host of `url`, with the port
+/// only if one is given, as for different test servers.
+pub fn host_key(url: &str) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return String::new();
+    };
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// A circuit breaker per host.
+///
+/// A closed breaker lets every request through. When too many of the
+/// recent requests failed it opens, and requests are skipped until the
+/// cooldown is over; then a single probe request is let through, which
+/// closes the breaker again if it succeeds and reopens it otherwise.
+#[derive(Debug)]
+pub struct Breakers {
+    config: BreakerConfig,
+    hosts: Hosts,
+}
+
+impl Breakers {
+    pub fn new(config: BreakerConfig) -> Self {
+        Breakers {
+            config,
+            hosts: Arc::default(),
+        }
+    }
+
+    /// Whether a request to `url` may be sent now.
+    pub fn admit(&self, url: &str) -> Option<Permit> {
+        let key = host_key(url);
+        let mut hosts = self.hosts.lock().unwrap();
+        let host = hosts.entry(key.clone()).or_default();
+        let probe = match host.state {
+            State::Closed => false,
+            State::Open { until } if Instant::now() >= until => {
+                host.state = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => return None,
+        };
+
+        Some(Permit {
+            hosts: self.hosts.clone(),
+            host: key,
+            probe,
+        })
+    }
+
+    /// Whether requests to `url` are skipped right now, without taking
+    /// the probe if one is due.
+    pub fn is_open(&self, url: &str) -> bool {
+        let hosts = self.hosts.lock().unwrap();
+        match hosts.get(&host_key(url)).map(|host| host.state) {
+            None | Some(State::Closed) => false,
+            Some(State::Open { until }) => Instant::now() < until,
+            Some(State::HalfOpen) => true,
+        }
+    }
+
+    /// Count a URL skipped because the breaker of its host was open.
+    pub fn skip(&self, url: &str) -> CircuitOpen {
+        let key = host_key(url);
+        self.hosts
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .skipped += 1;
+
+        CircuitOpen { host: key }
+    }
+
+    pub fn record(&self, mut permit: Permit, result: &Result<Fetched, FetchError>) {
+        let failed = match result {
+            Ok(fetched) => {
+                fetched.status.is_server_error()
+                    || self
+                        .config
+                        .max_latency
+                        .is_some_and(|max| fetched.stats.elapsed_time > max)
+            }
+            Err(_) => true,
+        };
+
+        // the probe is answered, so dropping the permit has nothing to undo
+        let probe = std::mem::take(&mut permit.probe);
+        let mut hosts = self.hosts.lock().unwrap();
+        let host = hosts.entry(permit.host.clone()).or_default();
+        let open = State::Open {
+            until: Instant::now() + self.config.cooldown,
+        };
+
+        if probe {
+            // a failed probe opens the breaker again, which is another trip
+            if failed {
+                host.state = open;
+                host.trips += 1;
+            } else {
+                host.state = State::Closed;
+            }
+            host.recent.clear();
+            return;
+        }
+        // requests sent before the breaker opened don't change anything
+        if host.state != State::Closed {
+            return;
+        }
+
+        host.recent.push_back(failed);
+        if host.recent.len() > self.config.window {
+            host.recent.pop_front();
+        }
+        let failures = host.recent.iter().filter(|failed| **failed).count();
+        if host.recent.len() >= self.config.window
+            && failures as f64 >= self.config.error_rate * host.recent.len() as f64
+        {
+            host.state = open;
+            host.trips += 1;
+            host.recent.clear();
+        }
+    }
+
+    pub fn report(&self) -> BreakerReport {
+        let hosts = self.hosts.lock().unwrap();
+        BreakerReport {
+            hosts: hosts
+                .iter()
+                .filter(|(_, host)| host.trips > 0 || host.skipped > 0)
+                .map(|(key, host)| {
+                    let report = HostReport {
+                        trips: host.trips,
+                        skipped: host.skipped,
+                        open: host.state != State::Closed,
+                    };
+                    (key.clone(), report)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HostReport {
+    /// How often the breaker opened.
+    pub trips: usize,
+    /// Requests skipped while it was open.
+    pub skipped: usize,
+    /// Whether it was still open at the end.
+    pub open: bool,
+}
+
+/// Hosts whose breaker opened during a run.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BreakerReport {
+    pub hosts: BTreeMap<String, HostReport>,
+}
+
+impl BreakerReport {
+    pub fn is_empty(&self) -> bool {
+        self.hosts.is_empty()
+    }
+}
+
+impl fmt::Display for BreakerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "circuit breakers: {} trips, {} requests skipped",
+            self.hosts.values().map(|host| host.trips).sum::<usize>(),
+            self.hosts.values().map(|host| host.skipped).sum::<usize>()
+        )?;
+        for (host, report) in &self.hosts {
+            writeln!(
+                f,
+                "  {:<30} {:>4} trips {:>8} skipped{}",
+                host,
+                report.trips,
+                report.skipped,
+                if report.open { "  (still open)" } else { "" }
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::breaker::{BreakerConfig, Breakers, HostReport};
+    use crate::fetch::{FetchError, Fetched};
+    use std::time::Duration;
+
+    const URL: &str = "https://example.com/";
+
+    fn send(breakers: &Breakers, result: Result<Fetched, FetchError>) -> bool {
+        match breakers.admit(URL) {
+            Some(permit) => {
+                breakers.record(permit, &result);
+                true
+            }
+            None => {
+                breakers.skip(URL);
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn test_breaker() {
+        let config = BreakerConfig {
+            error_rate: 0.5,
+            max_latency: Some(Duration::from_millis(500)),
+            window: 4,
+            cooldown: Duration::ZERO,
+        };
+
+        let breakers = Breakers::new(config);
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        assert!(send(&breakers, Ok(Fetched::with_status(503).took(10))));
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        assert!(breakers.report().is_empty());
+        // a slow response is the second failure of four
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(900))));
+
+        // the cooldown is over right away, so the next request is a probe,
+        // and the one after that is skipped while it fails
+        let probe = breakers.admit(URL).unwrap();
+        assert!(!send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        // a failed probe opens it again
+        breakers.record(probe, &Err("timeout".into()));
+        // a successful probe closes the breaker again
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(10))));
+
+        let report = breakers.report();
+        assert_eq!(
+            report.hosts["example.com"],
+            HostReport {
+                trips: 2,
+                skipped: 1,
+                open: false
+            }
+        );
+
+        let breakers = Breakers::new(BreakerConfig {
+            cooldown: Duration::from_secs(60),
+            ..config
+        });
+        for _ in 0..4 {
+            send(&breakers, Err("reset".into()));
+        }
+        assert!(!send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        assert!(breakers.report().hosts["example.com"].open);
+        assert!(breakers.is_open(URL));
+    }
+
+    #[test]
+    fn test_dropped_probe() {
+        let breakers = Breakers::new(BreakerConfig {
+            window: 1,
+            cooldown: Duration::ZERO,
+            ..BreakerConfig::default()
+        });
+        assert!(send(&breakers, Err("reset".into())));
+
+        // a cancelled probe hands the probe on to the next request
+        let probe = breakers.admit(URL).unwrap();
+        assert!(breakers.is_open(URL));
+        drop(probe);
+        assert!(!breakers.is_open(URL));
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        assert!(send(&breakers, Ok(Fetched::with_status(200).took(10))));
+        assert_eq!(breakers.report().hosts["example.com"].trips, 1);
+    }
+}
@@ -0,0 +1,235 @@
+use crate::fetch::{FetchError, Fetched};
+use reqwest::header::{CACHE_CONTROL, ETAG, HeaderMap, LAST_MODIFIED, PRAGMA, SET_COOKIE, VARY};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+// how many URLs the summary lists per reason
+const EXAMPLES: usize = 5;
+
+/// Why a shared cache (a CDN or proxy) won't store a response, or won't be
+/// able to revalidate it.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Reason {
+    /// `Cache-Control: private`
+    Private,
+    /// `Cache-Control: no-store`
+    NoStore,
+    /// `max-age=0`, or `s-maxage=0`, which shared caches go by instead.
+    MaxAgeZero,
+    /// Most CDNs don't cache responses that set cookies.
+    SetCookie,
+    /// `Vary: *` matches no later request.
+    VaryStar,
+    /// `Pragma: no-cache` without any `Cache-Control`.
+    Pragma,
+    /// Neither `ETag` nor `Last-Modified`, so every refresh is a full fetch.
+    NoValidators,
+}
+
+impl Reason {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reason::Private => "private",
+            Reason::NoStore => "no-store",
+            Reason::MaxAgeZero => "max-age=0",
+            Reason::SetCookie => "set-cookie",
+            Reason::VaryStar => "vary: *",
+            Reason::Pragma => "pragma: no-cache",
+            Reason::NoValidators => "no validators",
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn values(headers: &HeaderMap, name: impl reqwest::header::AsHeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|value| value.trim().to_ascii_lowercase())
+        .collect()
+}
+
+/// What keeps a response with these headers out of shared caches.
+pub fn lint(headers: &HeaderMap) -> Vec<Reason> {
+    let cache_control = values(headers, CACHE_CONTROL);
+    let directive = |name: &str| {
+        cache_control
+            .iter()
+            .find_map(|directive| match directive.split_once('=') {
+                Some((key, value)) if key.trim() == name => {
+                    Some(Some(value.trim().trim_matches('"')))
+                }
+                None if directive == name => Some(None),
+                _ => None,
+            })
+    };
+    let zero = |name| {
+        directive(name)
+            .flatten()
+            .is_some_and(|age| age.parse() == Ok(0u64))
+    };
+
+    let mut reasons = Vec::new();
+    if directive("private").is_some() {
+        reasons.push(Reason::Private);
+    }
+    if directive("no-store").is_some() {
+        reasons.push(Reason::NoStore);
+    }
+    let max_age_zero = match directive("s-maxage") {
+        Some(_) => zero("s-maxage"),
+        None => zero("max-age"),
+    };
+    if max_age_zero {
+        reasons.push(Reason::MaxAgeZero);
+    }
+    if headers.contains_key(SET_COOKIE) {
+        reasons.push(Reason::SetCookie);
+    }
+    if values(headers, VARY).iter().any(|value| value == "*") {
+        reasons.push(Reason::VaryStar);
+    }
+    if cache_control.is_empty()
+        && values(headers, PRAGMA)
+            .iter()
+            .any(|value| value == "no-cache")
+    {
+        reasons.push(Reason::Pragma);
+    }
+    if !headers.contains_key(ETAG) && !headers.contains_key(LAST_MODIFIED) {
+        reasons.push(Reason::NoValidators);
+    }
+
+    reasons
+}
+
+/// URLs whose successful responses shared caches won't store, by reason.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CacheabilityReport {
+    /// URLs with a successful response checked.
+    pub checked: usize,
+    pub by_reason: BTreeMap<Reason, BTreeSet<String>>,
+    // a URL is fetched once per route and again by each strategy
+    seen: HashSet<String>,
+}
+
+impl CacheabilityReport {
+    /// Only 2xx responses are checked; what an error or redirect says
+    /// about caching isn't what warming is after.
+    pub fn record(&mut self, url: &str, result: &Result<Fetched, FetchError>) {
+        let Ok(fetched) = result else {
+            return;
+        };
+        if !fetched.status.is_success() {
+            return;
+        }
+
+        if self.seen.insert(url.to_string()) {
+            self.checked += 1;
+        }
+        for reason in lint(&fetched.headers) {
+            self.by_reason
+                .entry(reason)
+                .or_default()
+                .insert(url.to_string());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_reason.is_empty()
+    }
+}
+
+impl fmt::Display for CacheabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "cacheability of {} urls:", self.checked)?;
+        for (reason, urls) in &self.by_reason {
+            writeln!(f, "  {:<18} {:>6} urls", reason, urls.len())?;
+            for url in urls.iter().take(EXAMPLES) {
+                writeln!(f, "    {}", url)?;
+            }
+            if urls.len() > EXAMPLES {
+                writeln!(f, "    and {} more", urls.len() - EXAMPLES)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cacheability::{CacheabilityReport, Reason, lint};
+    use crate::fetch::Fetched;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+    fn headers(headers: &[(&str, &str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.parse::<HeaderName>().unwrap(),
+                    HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let cacheable = headers(&[("cache-control", "public, max-age=3600"), ("etag", "\"1\"")]);
+        assert_eq!(lint(&cacheable), []);
+
+        assert_eq!(
+            lint(&headers(&[
+                ("cache-control", "Private, max-age=0"),
+                ("cache-control", "no-store"),
+                ("set-cookie", "session=1"),
+                ("vary", "Accept-Encoding, *"),
+            ])),
+            [
+                Reason::Private,
+                Reason::NoStore,
+                Reason::MaxAgeZero,
+                Reason::SetCookie,
+                Reason::VaryStar,
+                Reason::NoValidators
+            ]
+        );
+        // shared caches go by s-maxage
+        assert_eq!(
+            lint(&headers(&[
+                ("cache-control", "max-age=0, s-maxage=\"600\""),
+                ("last-modified", "Tue, 15 Oct 2025 07:28:00 GMT"),
+            ])),
+            []
+        );
+        assert_eq!(
+            lint(&headers(&[("pragma", "no-cache"), ("etag", "\"1\"")])),
+            [Reason::Pragma]
+        );
+
+        let mut report = CacheabilityReport::default();
+        let cacheable = Fetched::with_status(200)
+            .header("cache-control", "public, max-age=3600")
+            .header("etag", "\"1\"");
+        report.record("https://example.com/a", &Ok(cacheable));
+        report.record("https://example.com/b", &Ok(Fetched::with_status(200)));
+        // the same URL through another route or strategy counts once
+        report.record("https://example.com/b", &Ok(Fetched::with_status(200)));
+        report.record("https://example.com/c", &Ok(Fetched::with_status(404)));
+        report.record("https://example.com/d", &Err("reset".into()));
+        assert_eq!(report.checked, 2);
+        assert_eq!(
+            Vec::from_iter(&report.by_reason[&Reason::NoValidators]),
+            ["https://example.com/b"]
+        );
+    }
+}
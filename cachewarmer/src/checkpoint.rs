@@ -0,0 +1,194 @@
+use crate::stats::Stats;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Which URLs of a list have been warmed, and the totals gathered for them.
+///
+/// URLs are identified by their position in the list, so duplicate lines
+/// are tracked separately. Positions are kept as sorted, non-overlapping
+/// `[start, end)` ranges, which stays compact even though concurrent
+/// requests complete out of order. A hash of the whole list makes sure the
+/// positions are resumed against the list they were recorded for.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub url_count: usize,
+    /// SHA-256 of the URLs in list order, empty if the list isn't known.
+    #[serde(default)]
+    pub list_hash: String,
+    pub totals: Stats,
+    done: Vec<(usize, usize)>,
+}
+
+fn list_hash<S: AsRef<str>>(urls: impl IntoIterator<Item = S>) -> (usize, String) {
+    let mut hasher = Sha256::new();
+    let mut count = 0;
+    for url in urls {
+        hasher.update(url.as_ref().as_bytes());
+        hasher.update(b"\n");
+        count += 1;
+    }
+
+    (count, format!("{:x}", hasher.finalize()))
+}
+
+impl Checkpoint {
+    /// A checkpoint that is never resumed, only counting the URLs.
+    pub fn new(url_count: usize) -> Self {
+        Checkpoint {
+            url_count,
+            ..Default::default()
+        }
+    }
+
+    /// A checkpoint for `urls`, in list order, to be saved and resumed.
+    pub fn for_urls<S: AsRef<str>>(urls: impl IntoIterator<Item = S>) -> Self {
+        let (url_count, list_hash) = list_hash(urls);
+        Checkpoint {
+            url_count,
+            list_hash,
+            ..Default::default()
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    /// Load a checkpoint for resuming `urls`, in list order. Refuses one
+    /// recorded for any other list, even of the same length, as it would
+    /// skip the wrong URLs.
+    pub fn resume<P, S>(path: P, urls: impl IntoIterator<Item = S>) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+        S: AsRef<str>,
+    {
+        let checkpoint = Self::load(path)?;
+        let (url_count, list_hash) = list_hash(urls);
+        if checkpoint.url_count != url_count {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "checkpoint is for {} urls, the list has {}",
+                    checkpoint.url_count, url_count
+                ),
+            ));
+        }
+        if checkpoint.list_hash != list_hash {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "checkpoint is for a different list of urls",
+            ));
+        }
+
+        Ok(checkpoint)
+    }
+
+    /// Write the checkpoint next to `path` and rename it into place, so
+    /// a crash mid-write never leaves a truncated file behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let path = path.as_ref();
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        let mut file = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(&mut file, self)?;
+        file.flush()?;
+        file.get_ref().sync_all()?;
+
+        std::fs::rename(&tmp_path, path)
+    }
+
+    pub fn is_done(&self, index: usize) -> bool {
+        let pos = self.done.partition_point(|&(start, _)| start <= index);
+        pos > 0 && index < self.done[pos - 1].1
+    }
+
+    pub fn done_count(&self) -> usize {
+        self.done.iter().map(|(start, end)| end - start).sum()
+    }
+
+    pub fn record(&mut self, index: usize, stats: &Stats) {
+        if self.is_done(index) {
+            return;
+        }
+
+        self.totals.aggregate(stats);
+
+        let pos = self.done.partition_point(|&(start, _)| start <= index);
+        let joins_prev = pos > 0 && self.done[pos - 1].1 == index;
+        let joins_next = pos < self.done.len() && self.done[pos].0 == index + 1;
+
+        match (joins_prev, joins_next) {
+            (true, true) => {
+                self.done[pos - 1].1 = self.done[pos].1;
+                self.done.remove(pos);
+            }
+            (true, false) => self.done[pos - 1].1 = index + 1,
+            (false, true) => self.done[pos].0 = index,
+            (false, false) => self.done.insert(pos, (index, index + 1)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::Checkpoint;
+    use crate::stats::Stats;
+    use std::time::Duration;
+
+    fn stats(millis: u64) -> Stats {
+        Stats {
+            elapsed_time: Duration::from_millis(millis),
+            content_length: 100,
+            ..Stats::new()
+        }
+    }
+
+    #[test]
+    fn test_checkpoint_out_of_order() {
+        let mut checkpoint = Checkpoint::new(6);
+        for index in [4, 0, 2, 1, 5] {
+            checkpoint.record(index, &stats(10));
+        }
+
+        assert_eq!(checkpoint.done, vec![(0, 3), (4, 6)]);
+        assert_eq!(checkpoint.done_count(), 5);
+        assert!(checkpoint.is_done(2));
+        assert!(!checkpoint.is_done(3));
+        assert!(!checkpoint.is_done(6));
+        assert_eq!(checkpoint.totals.content_length, 500);
+
+        checkpoint.record(3, &stats(10));
+        assert_eq!(checkpoint.done, vec![(0, 6)]);
+    }
+
+    #[test]
+    fn test_checkpoint_record_twice() {
+        let mut checkpoint = Checkpoint::new(2);
+        checkpoint.record(1, &stats(10));
+        checkpoint.record(1, &stats(10));
+
+        assert_eq!(checkpoint.done_count(), 1);
+        assert_eq!(checkpoint.totals, stats(10));
+    }
+
+    #[test]
+    fn test_checkpoint_save_resume() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let urls = ["/a", "/b", "/c"];
+        let mut checkpoint = Checkpoint::for_urls(urls);
+        checkpoint.record(1, &stats(10));
+        checkpoint.save(&path).unwrap();
+
+        assert_eq!(Checkpoint::resume(&path, urls).unwrap(), checkpoint);
+        assert!(Checkpoint::resume(&path, ["/a", "/b", "/c", "/d"]).is_err());
+        // same length, different urls
+        assert!(Checkpoint::resume(&path, ["/a", "/c", "/b"]).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+use crate::fetch::{FetchError, Fetched};
+use crate::progress::percentile;
+use crate::stats::Stats;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Everything seen for one URL during a run.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UrlResult {
+    /// Latencies of the requests that got a response.
+    pub latencies: Vec<Duration>,
+    /// Body size of the last response.
+    pub content_length: usize,
+    /// Status of the last response.
+    pub status: Option<u16>,
+    /// Requests that got no response at all.
+    pub errors: usize,
+}
+
+impl UrlResult {
+    fn merge(&mut self, other: &UrlResult) {
+        self.latencies.extend_from_slice(&other.latencies);
+        if other.status.is_some() {
+            self.content_length = other.content_length;
+            self.status = other.status;
+        }
+        self.errors += other.errors;
+    }
+
+    fn sorted_latencies(&self) -> Vec<Duration> {
+        let mut latencies = self.latencies.clone();
+        latencies.sort();
+        latencies
+    }
+}
+
+/// Per-URL results and totals of a run, which can be saved as a baseline
+/// for later runs.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct RunResults {
+    pub totals: Stats,
+    pub urls: BTreeMap<String, UrlResult>,
+}
+
+impl RunResults {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.flush()
+    }
+
+    pub fn record(&mut self, url: &str, result: &Result<Fetched, FetchError>) {
+        let url_result = self.urls.entry(url.to_string()).or_default();
+        match result {
+            Ok(fetched) => {
+                self.totals.aggregate(&fetched.stats);
+                url_result.latencies.push(fetched.stats.elapsed_time);
+                url_result.content_length = fetched.stats.content_length;
+                url_result.status = Some(fetched.status.as_u16());
+            }
+            Err(_) => url_result.errors += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &RunResults) {
+        self.totals.aggregate(&other.totals);
+        for (url, result) in &other.urls {
+            self.urls.entry(url.clone()).or_default().merge(result);
+        }
+    }
+
+    /// Compare with a `baseline` run, flagging latency increases of more
+    /// than `threshold` percent.
+    pub fn compare(&self, baseline: &RunResults, threshold: f64) -> Comparison {
+        let mut changes = Vec::new();
+        let mut added = Vec::new();
+        let mut all_old = Vec::new();
+        let mut all_new = Vec::new();
+
+        for (url, new) in &self.urls {
+            all_new.extend_from_slice(&new.latencies);
+            match baseline.urls.get(url) {
+                Some(old) => changes.push(Change::new(
+                    url,
+                    &old.sorted_latencies(),
+                    &new.sorted_latencies(),
+                    (old.content_length, new.content_length),
+                    (old.status, new.status),
+                    threshold,
+                )),
+                None => added.push(url.clone()),
+            }
+        }
+
+        let mut removed = Vec::new();
+        for (url, old) in &baseline.urls {
+            all_old.extend_from_slice(&old.latencies);
+            if !self.urls.contains_key(url) {
+                removed.push(url.clone());
+            }
+        }
+
+        all_old.sort();
+        all_new.sort();
+        let total = Change::new(
+            "total",
+            &all_old,
+            &all_new,
+            (baseline.totals.content_length, self.totals.content_length),
+            (None, None),
+            threshold,
+        );
+
+        Comparison {
+            changes,
+            total,
+            added,
+            removed,
+        }
+    }
+}
+
+fn percent_change(old: f64, new: f64) -> Option<f64> {
+    if old == 0.0 {
+        return None;
+    }
+
+    Some((new - old) / old * 100.0)
+}
+
+fn is_success(status: Option<u16>) -> bool {
+    matches!(status, Some(200..=399))
+}
+
+/// How one URL (or the whole run) changed against the baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    pub url: String,
+    pub p50: (Option<Duration>, Option<Duration>),
+    pub p99: (Option<Duration>, Option<Duration>),
+    pub content_length: (usize, usize),
+    pub status: (Option<u16>, Option<u16>),
+    pub regressions: Vec<String>,
+}
+
+impl Change {
+    fn new(
+        url: &str,
+        old: &[Duration],
+        new: &[Duration],
+        content_length: (usize, usize),
+        status: (Option<u16>, Option<u16>),
+        threshold: f64,
+    ) -> Self {
+        let mut change = Change {
+            url: url.to_string(),
+            p50: (percentile(old, 50.0), percentile(new, 50.0)),
+            p99: (percentile(old, 99.0), percentile(new, 99.0)),
+            content_length,
+            status,
+            regressions: Vec::new(),
+        };
+
+        for (name, (old, new)) in [("p50", change.p50), ("p99", change.p99)] {
+            if let (Some(old), Some(new)) = (old, new) {
+                let delta = percent_change(old.as_secs_f64(), new.as_secs_f64());
+                if delta.is_some_and(|delta| delta > threshold) {
+                    change.regressions.push(format!(
+                        "{} latency {:?} -> {:?} ({:+.1}%)",
+                        name,
+                        old,
+                        new,
+                        delta.unwrap()
+                    ));
+                }
+            }
+        }
+
+        if is_success(status.0) && !is_success(status.1) {
+            change.regressions.push(match status.1 {
+                Some(new) => format!("status {} -> {}", status.0.unwrap(), new),
+                None => format!("status {} -> no response", status.0.unwrap()),
+            });
+        }
+
+        change
+    }
+
+    pub fn is_regression(&self) -> bool {
+        !self.regressions.is_empty()
+    }
+}
+
+fn fmt_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{:.1}ms", latency.as_secs_f64() * 1000.0),
+        None => "-".to_string(),
+    }
+}
+
+fn fmt_status(status: Option<u16>) -> String {
+    match status {
+        Some(status) => status.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} p50 {} -> {}, p99 {} -> {}, size {} -> {}",
+            if self.is_regression() { "!" } else { " " },
+            self.url,
+            fmt_latency(self.p50.0),
+            fmt_latency(self.p50.1),
+            fmt_latency(self.p99.0),
+            fmt_latency(self.p99.1),
+            self.content_length.0,
+            self.content_length.1,
+        )?;
+
+        if self.status.0 != self.status.1 {
+            write!(
+                f,
+                ", status {} -> {}",
+                fmt_status(self.status.0),
+                fmt_status(self.status.1)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A run compared against a baseline.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub changes: Vec<Change>,
+    pub total: Change,
+    /// URLs not in the baseline.
+    pub added: Vec<String>,
+    /// Baseline URLs missing from this run.
+    pub removed: Vec<String>,
+}
+
+impl Comparison {
+    pub fn regressions(&self) -> impl Iterator<Item = &Change> {
+        self.changes
+            .iter()
+            .chain(std::iter::once(&self.total))
+            .filter(|change| change.is_regression())
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        for url in &self.added {
+            writeln!(f, "+ {}", url)?;
+        }
+        for url in &self.removed {
+            writeln!(f, "- {}", url)?;
+        }
+        writeln!(f, "{}", self.total)?;
+
+        for change in self.regressions() {
+            for regression in &change.regressions {
+                writeln!(f, "REGRESSION {}: {}", change.url, regression)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{RunResults, UrlResult};
+    use crate::stats::Stats;
+    use std::time::Duration;
+
+    fn results(urls: &[(&str, u64, usize, Option<u16>)]) -> RunResults {
+        let mut results = RunResults::default();
+        for &(url, millis, content_length, status) in urls {
+            let latency = Duration::from_millis(millis);
+            results.totals.aggregate(&Stats {
+                elapsed_time: latency,
+                content_length,
+                ..Stats::new()
+            });
+            results.urls.insert(
+                url.to_string(),
+                UrlResult {
+                    latencies: vec![latency],
+                    content_length,
+                    status,
+                    errors: status.is_none() as usize,
+                },
+            );
+        }
+        results
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = results(&[
+            ("/a", 100, 1000, Some(200)),
+            ("/b", 100, 1000, Some(200)),
+            ("/c", 100, 1000, Some(200)),
+            ("/gone", 100, 1000, Some(200)),
+        ]);
+        let current = results(&[
+            ("/a", 105, 1000, Some(200)),
+            ("/b", 150, 1200, Some(200)),
+            ("/c", 50, 0, Some(503)),
+            ("/new", 100, 1000, Some(200)),
+        ]);
+
+        let comparison = current.compare(&baseline, 10.0);
+        let regressed: Vec<_> = comparison
+            .regressions()
+            .map(|change| change.url.as_str())
+            .collect();
+        assert_eq!(regressed, ["/b", "/c", "total"]);
+        assert_eq!(comparison.changes[1].regressions.len(), 2);
+        assert_eq!(comparison.changes[2].regressions, ["status 200 -> 503"]);
+        assert_eq!(comparison.added, ["/new"]);
+        assert_eq!(comparison.removed, ["/gone"]);
+        assert_eq!(comparison.total.content_length, (4000, 3200));
+
+        assert_eq!(current.compare(&current, 10.0).regressions().count(), 0);
+    }
+}
@@ -0,0 +1,526 @@
+use crate::adaptive::{AdaptiveConfig, ConcurrencyReport, Limits};
+use crate::assertions::{Assertion, Observed, check_all};
+use crate::auth::Auth;
+use crate::breaker::{BreakerConfig, BreakerReport, Breakers};
+use crate::origin::Origin;
+use crate::progress::Progress;
+use crate::proxy::ProxyPool;
+use crate::redirect::{self, Hop, RedirectPolicy};
+use crate::stats::Stats;
+use crate::timing::{self, PhaseStats, Phases};
+use crate::tls::CertReport;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, LOCATION};
+use reqwest::{StatusCode, Url};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub type FetchError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A URL to warm, at `index` in the URL list.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub index: usize,
+    pub url: String,
+    pub assertions: Vec<Assertion>,
+    /// Jobs with a higher priority are started first.
+    pub priority: f64,
+}
+
+impl Job {
+    /// Order `jobs` to start those with the highest priority first, and the
+    /// rest in list order.
+    pub fn sort_by_priority(jobs: &mut [Job]) {
+        jobs.sort_by(|a, b| b.priority.total_cmp(&a.priority));
+    }
+}
+
+/// A plain URL, without assertions and with the default priority; its
+/// index is up to where it is listed.
+impl From<String> for Job {
+    fn from(url: String) -> Self {
+        Job {
+            index: 0,
+            url,
+            assertions: Vec::new(),
+            priority: 0.0,
+        }
+    }
+}
+
+impl From<&str> for Job {
+    fn from(url: &str) -> Self {
+        Job::from(url.to_string())
+    }
+}
+
+/// A response that was received in full.
+#[derive(Clone, Debug)]
+pub struct Fetched {
+    /// Covers the whole redirect chain.
+    pub stats: Stats,
+    /// Where the final response came from.
+    pub url: String,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// Redirects followed on the way, in order.
+    pub redirects: Vec<Hop>,
+    /// Connection phases of the final request.
+    pub phases: Phases,
+    /// Hex SHA-256 of the body, hashed as it came in.
+    pub sha256: String,
+    /// Assertions that did not hold.
+    pub failures: Vec<String>,
+}
+
+impl Fetched {
+    /// The media type, without parameters such as the charset.
+    pub fn content_type(&self) -> Option<&str> {
+        let value = self.headers.get(CONTENT_TYPE)?.to_str().ok()?;
+        Some(value.split(';').next().unwrap_or_default().trim())
+    }
+
+    /// Every URL redirected to, whether the redirect was followed or not.
+    pub fn redirect_targets(&self) -> Vec<String> {
+        let mut targets: Vec<_> = self
+            .redirects
+            .iter()
+            .map(|hop| hop.location.clone())
+            .collect();
+        if self.status.is_redirection() {
+            let location = self
+                .headers
+                .get(LOCATION)
+                .and_then(|value| value.to_str().ok());
+            let target = Url::parse(&self.url)
+                .ok()
+                .zip(location)
+                .and_then(|(url, location)| url.join(location).ok());
+            targets.extend(target.map(String::from));
+        }
+
+        targets
+    }
+}
+
+#[cfg(test)]
+impl Fetched {
+    /// An empty response with `status`, for tests to fill in the rest.
+    pub fn with_status(status: u16) -> Self {
+        Fetched {
+            stats: Stats::new(),
+            url: String::new(),
+            status: StatusCode::from_u16(status).unwrap(),
+            headers: HeaderMap::new(),
+            redirects: Vec::new(),
+            phases: Phases::default(),
+            sha256: String::new(),
+            failures: Vec::new(),
+        }
+    }
+
+    pub fn took(mut self, millis: u64) -> Self {
+        self.stats.elapsed_time = Duration::from_millis(millis);
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name: reqwest::header::HeaderName = name.parse().unwrap();
+        self.headers.append(name, value.parse().unwrap());
+        self
+    }
+
+    /// A body of `body`, which also stands in for its hash so that
+    /// differences are easy to read.
+    pub fn body(mut self, body: &str) -> Self {
+        self.stats.content_length = body.len();
+        self.sha256 = body.to_string();
+        self
+    }
+}
+
+/// What happened to a URL behind one proxy (or going direct).
+#[derive(Debug)]
+pub struct RouteResult {
+    pub proxy: String,
+    pub result: Result<Fetched, FetchError>,
+}
+
+/// What happened to a URL, behind every proxy it was sent through.
+#[derive(Debug)]
+pub struct Outcome {
+    pub index: usize,
+    pub url: String,
+    pub routes: Vec<RouteResult>,
+    /// Redirect targets warmed as entries of their own.
+    pub targets: Vec<Outcome>,
+    /// The same URL fetched from the origin, if compared with it.
+    pub origin: Option<Result<Fetched, FetchError>>,
+}
+
+impl Outcome {
+    pub fn is_ok(&self) -> bool {
+        self.routes.iter().all(|route| route.result.is_ok())
+    }
+
+    /// Combined stats of the routes that succeeded.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::new();
+        for route in &self.routes {
+            if let Ok(fetched) = &route.result {
+                stats.aggregate(&fetched.stats);
+            }
+        }
+
+        stats
+    }
+}
+
+/// Everything needed to warm a single URL: the clients (one per proxy),
+/// credentials and the places where side results are collected.
+///
+/// All execution strategies share this, and differ only in how they
+/// schedule calls to [`Fetcher::fetch`].
+pub struct Fetcher {
+    pool: ProxyPool,
+    auth: Option<Arc<Auth>>,
+    certs: Arc<Mutex<CertReport>>,
+    progress: Arc<Progress>,
+    retries: usize,
+    retry_backoff: Duration,
+    redirects: RedirectPolicy,
+    // redirect targets already warmed, if they are to be warmed at all
+    warmed_targets: Option<Mutex<HashSet<String>>>,
+    breakers: Option<Breakers>,
+    limits: Option<Limits>,
+    origin: Option<(reqwest::Client, Origin)>,
+}
+
+impl Fetcher {
+    pub fn new(
+        pool: ProxyPool,
+        auth: Option<Arc<Auth>>,
+        certs: Arc<Mutex<CertReport>>,
+        progress: Arc<Progress>,
+    ) -> Self {
+        Fetcher {
+            pool,
+            auth,
+            certs,
+            progress,
+            retries: 0,
+            retry_backoff: Duration::ZERO,
+            redirects: RedirectPolicy::default(),
+            warmed_targets: None,
+            breakers: None,
+            limits: None,
+            origin: None,
+        }
+    }
+
+    /// Set which redirects to follow, and whether to also warm every
+    /// redirect target (once per run) as an entry of its own.
+    pub fn set_redirects(&mut self, policy: RedirectPolicy, warm_targets: bool) {
+        self.redirects = policy;
+        self.warmed_targets = warm_targets.then(|| Mutex::new(HashSet::new()));
+    }
+
+    /// Retry failed requests (not failed assertions) up to `retries` times,
+    /// doubling the wait between attempts starting at `backoff`.
+    pub fn set_retries(&mut self, retries: usize, backoff: Duration) {
+        self.retries = retries;
+        self.retry_backoff = backoff;
+    }
+
+    /// Stop sending requests to hosts that fail too often, for a while.
+    pub fn set_breaker(&mut self, config: BreakerConfig) {
+        self.breakers = Some(Breakers::new(config));
+    }
+
+    /// Hosts whose circuit breaker opened so far.
+    pub fn breaker_report(&self) -> BreakerReport {
+        self.breakers
+            .as_ref()
+            .map(Breakers::report)
+            .unwrap_or_default()
+    }
+
+    /// Limit the requests in flight to each host, adapting the limit to
+    /// how the host copes.
+    pub fn set_adaptive(&mut self, config: AdaptiveConfig) {
+        self.limits = Some(Limits::new(config));
+    }
+
+    /// Also fetch every URL from `origin`, with a client set up to reach it,
+    /// to compare the responses through the CDN with.
+    pub fn set_origin(&mut self, client: reqwest::Client, origin: Origin) {
+        self.origin = Some((client, origin));
+    }
+
+    /// How the concurrency limits changed so far, if they adapt.
+    pub fn concurrency_report(&self) -> ConcurrencyReport {
+        self.limits.as_ref().map(Limits::report).unwrap_or_default()
+    }
+
+    pub fn pool(&self) -> &ProxyPool {
+        &self.pool
+    }
+
+    pub fn progress(&self) -> &Arc<Progress> {
+        &self.progress
+    }
+
+    async fn get(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        assertions: &[Assertion],
+    ) -> Result<Fetched, FetchError> {
+        let start = Instant::now();
+        let mut url = Url::parse(url)?;
+        let mut redirects = Vec::new();
+        let mut phase_stats = PhaseStats::default();
+
+        let (mut resp, phases) = loop {
+            let hop_start = Instant::now();
+            let mut request = client.get(url.clone());
+            if let Some(auth) = &self.auth
+                && auth.applies_to(&url)
+            {
+                request = auth.apply(client, request).await?;
+            }
+            let (resp, phases) = timing::measure(request.send()).await;
+            let resp = resp?;
+            phase_stats.record(&phases);
+            self.certs.lock().unwrap().record(&resp);
+
+            let Some(location) = redirect::location(&resp) else {
+                break (resp, phases);
+            };
+            if !self.redirects.allows(redirects.len(), &url, &location) {
+                break (resp, phases);
+            }
+
+            let status = resp.status();
+            // read the body so that the connection can be reused
+            resp.bytes().await?;
+            redirects.push(Hop {
+                url: url.to_string(),
+                status: status.as_u16(),
+                elapsed_time: hop_start.elapsed(),
+                location: location.to_string(),
+            });
+            url = location;
+        };
+
+        let status = resp.status();
+        let headers = resp.headers().clone();
+
+        // can't rely on .content_length()
+        let mut body = Vec::new();
+        let mut hasher = Sha256::new();
+        while let Some(chunk) = resp.chunk().await? {
+            hasher.update(&chunk);
+            body.extend_from_slice(&chunk);
+        }
+        let elapsed_time = start.elapsed();
+
+        let failures = check_all(
+            assertions,
+            &Observed {
+                status,
+                latency: elapsed_time,
+                headers: &headers,
+                body: &body,
+            },
+        );
+
+        let stats = Stats {
+            elapsed_time,
+            content_length: body.len(),
+            phases: phase_stats,
+        };
+
+        Ok(Fetched {
+            stats,
+            url: url.to_string(),
+            status,
+            headers,
+            redirects,
+            phases,
+            sha256: format!("{:x}", hasher.finalize()),
+            failures,
+        })
+    }
+
+    // none if the circuit breaker of the host is open
+    async fn attempt(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        assertions: &[Assertion],
+    ) -> Option<Result<Fetched, FetchError>> {
+        // a skipped request doesn't wait for a slot it won't use
+        let permit = match &self.breakers {
+            Some(breakers) => Some(breakers.admit(url)?),
+            None => None,
+        };
+        let slot = match &self.limits {
+            Some(limits) => Some(limits.acquire(url).await),
+            None => None,
+        };
+
+        let mut result = self.get(client, url, assertions).await;
+        // an expired or revoked OAuth2 token gets one more try with a new one
+        if let (Some(auth), Ok(fetched)) = (&self.auth, &result)
+            && fetched.status == StatusCode::UNAUTHORIZED
+            && auth.refreshes()
+            && Url::parse(&fetched.url).is_ok_and(|url| auth.applies_to(&url))
+        {
+            auth.invalidate(Instant::now()).await;
+            result = self.get(client, url, assertions).await;
+        }
+        if let Some(permit) = permit {
+            self.breakers.as_ref().unwrap().record(permit, &result);
+        }
+        if let Some(slot) = slot {
+            slot.release(&result);
+        }
+        Some(result)
+    }
+
+    // failures are tried again with backoff, unless the breaker opens
+    async fn retrying(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        assertions: &[Assertion],
+    ) -> Result<Fetched, FetchError> {
+        let mut backoff = self.retry_backoff;
+        let mut result = match self.attempt(client, url, assertions).await {
+            Some(result) => result,
+            None => Err(self.breakers.as_ref().unwrap().skip(url).into()),
+        };
+        for _ in 0..self.retries {
+            if result.is_ok() {
+                break;
+            }
+            // no use waiting to retry a host whose breaker opened meanwhile
+            if self
+                .breakers
+                .as_ref()
+                .is_some_and(|breakers| breakers.is_open(url))
+            {
+                break;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            match self.attempt(client, url, assertions).await {
+                Some(retried) => result = retried,
+                None => break,
+            }
+        }
+
+        result
+    }
+
+    // redirect targets are extra work, so they stay out of the progress
+    async fn fetch_routes(
+        &self,
+        url: &str,
+        assertions: &[Assertion],
+        track_progress: bool,
+    ) -> Vec<RouteResult> {
+        let routes = self
+            .pool
+            .routes()
+            .into_iter()
+            .map(|(proxy, client)| async move {
+                if track_progress {
+                    self.progress.started();
+                }
+                let result = self.retrying(client, url, assertions).await;
+                if track_progress {
+                    match &result {
+                        Ok(fetched) => self.progress.completed(fetched.stats.elapsed_time),
+                        Err(_) => self.progress.failed(),
+                    }
+                }
+
+                RouteResult {
+                    proxy: proxy.to_string(),
+                    result,
+                }
+            });
+
+        futures::future::join_all(routes).await
+    }
+
+    // targets of targets too, as far as a redirect chain may go
+    async fn fetch_targets(
+        &self,
+        outcome: &Outcome,
+        warmed: &Mutex<HashSet<String>>,
+    ) -> Vec<Outcome> {
+        let mut targets: Vec<Outcome> = Vec::new();
+        let mut round = 0;
+        for _ in 0..self.redirects.max_hops() {
+            let urls: Vec<_> = {
+                let mut warmed = warmed.lock().unwrap();
+                let sources = match round {
+                    0 => std::slice::from_ref(outcome),
+                    _ => &targets[targets.len() - round..],
+                };
+                sources
+                    .iter()
+                    .flat_map(|source| &source.routes)
+                    .filter_map(|route| route.result.as_ref().ok())
+                    .flat_map(Fetched::redirect_targets)
+                    .filter(|target| *target != outcome.url && warmed.insert(target.clone()))
+                    .collect()
+            };
+            if urls.is_empty() {
+                break;
+            }
+
+            let urls = urls.into_iter().map(|url| async move {
+                Outcome {
+                    index: outcome.index,
+                    routes: self.fetch_routes(&url, &[], false).await,
+                    url,
+                    targets: Vec::new(),
+                    origin: None,
+                }
+            });
+            let fetched = futures::future::join_all(urls).await;
+            round = fetched.len();
+            targets.extend(fetched);
+        }
+
+        targets
+    }
+
+    pub async fn fetch(&self, job: Job) -> Outcome {
+        let origin = async {
+            let (client, origin) = self.origin.as_ref()?;
+            Some(self.retrying(client, &origin.url(&job.url), &[]).await)
+        };
+        let (routes, origin) =
+            futures::join!(self.fetch_routes(&job.url, &job.assertions, true), origin);
+        let mut outcome = Outcome {
+            index: job.index,
+            url: job.url,
+            routes,
+            targets: Vec::new(),
+            origin,
+        };
+
+        if let Some(warmed) = &self.warmed_targets {
+            outcome.targets = self.fetch_targets(&outcome, warmed).await;
+        }
+
+        outcome
+    }
+}
@@ -0,0 +1,198 @@
+use crate::fetch::{FetchError, Fetched};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Write};
+use std::path::Path;
+
+// how much of a hash the report shows
+const SHORT_HASH: usize = 12;
+
+/// Body hashes of successful responses, per URL, with the edges (proxies)
+/// that served each: more than one hash for a URL means different edges,
+/// or the same edge at different times, served different content.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Fingerprints {
+    pub urls: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+}
+
+impl Fingerprints {
+    /// An empty set if the file isn't there yet, as on the first run.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        match File::open(path) {
+            Ok(file) => Ok(serde_json::from_reader(BufReader::new(file))?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Fingerprints::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.flush()
+    }
+
+    pub fn record(&mut self, url: &str, edge: &str, result: &Result<Fetched, FetchError>) {
+        let Ok(fetched) = result else {
+            return;
+        };
+        if !fetched.status.is_success() {
+            return;
+        }
+
+        let edges = self
+            .urls
+            .entry(url.to_string())
+            .or_default()
+            .entry(fetched.sha256.clone())
+            .or_default();
+        if !edges.iter().any(|seen| seen == edge) {
+            edges.push(edge.to_string());
+        }
+    }
+
+    pub fn merge(&mut self, other: &Fingerprints) {
+        for (url, hashes) in &other.urls {
+            let merged = self.urls.entry(url.clone()).or_default();
+            for (hash, edges) in hashes {
+                let merged = merged.entry(hash.clone()).or_default();
+                for edge in edges {
+                    if !merged.contains(edge) {
+                        merged.push(edge.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace the hashes of every URL seen in `latest`, keeping those of
+    /// the URLs it didn't fetch.
+    pub fn update(&mut self, latest: &Fingerprints) {
+        for (url, hashes) in &latest.urls {
+            self.urls.insert(url.clone(), hashes.clone());
+        }
+    }
+
+    /// URLs whose content changed since the `previous` run, and URLs
+    /// served with more than one body in this one.
+    pub fn drift(&self, previous: Option<&Fingerprints>) -> Drift {
+        let mut drift = Drift::default();
+        for (url, hashes) in &self.urls {
+            if hashes.len() > 1 {
+                drift.differing.push((url.clone(), hashes.clone()));
+            }
+
+            let old = previous.and_then(|previous| previous.urls.get(url));
+            if let Some(old) = old
+                && !old.keys().eq(hashes.keys())
+            {
+                let old = old.keys().cloned().collect();
+                let new = hashes.keys().cloned().collect();
+                drift.changed.push((url.clone(), old, new));
+            }
+        }
+
+        drift
+    }
+}
+
+/// Unexpected content changes, from [`Fingerprints::drift`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Drift {
+    /// URLs with their hashes in the previous run and in this one.
+    pub changed: Vec<(String, Vec<String>, Vec<String>)>,
+    /// URLs with more than one hash in this run, and the edges behind each.
+    pub differing: Vec<(String, BTreeMap<String, Vec<String>>)>,
+}
+
+impl Drift {
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.differing.is_empty()
+    }
+}
+
+/// The start of a hash, as much as reports show.
+pub fn short(hash: &str) -> &str {
+    &hash[..hash.len().min(SHORT_HASH)]
+}
+
+fn short_all(hashes: &[String]) -> String {
+    let hashes: Vec<_> = hashes.iter().map(|hash| short(hash)).collect();
+    hashes.join(",")
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "content fingerprints: {} urls changed since the last run, {} differ within this run",
+            self.changed.len(),
+            self.differing.len()
+        )?;
+        for (url, old, new) in &self.changed {
+            writeln!(
+                f,
+                "  changed {} {} -> {}",
+                url,
+                short_all(old),
+                short_all(new)
+            )?;
+        }
+        for (url, hashes) in &self.differing {
+            let edges: Vec<_> = hashes
+                .iter()
+                .map(|(hash, edges)| format!("{} {}", short(hash), edges.join(",")))
+                .collect();
+            writeln!(f, "  differs {} ({})", url, edges.join("; "))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetch::Fetched;
+    use crate::fingerprint::Fingerprints;
+
+    #[test]
+    fn test_drift() {
+        let mut previous = Fingerprints::default();
+        previous.record("/a", "direct", &Ok(Fetched::with_status(200).body("aaaa")));
+        previous.record("/b", "direct", &Ok(Fetched::with_status(200).body("bbbb")));
+        previous.record(
+            "/gone",
+            "direct",
+            &Ok(Fetched::with_status(200).body("gggg")),
+        );
+
+        let mut run = Fingerprints::default();
+        run.record("/a", "direct", &Ok(Fetched::with_status(200).body("aaaa")));
+        run.record("/b", "direct", &Ok(Fetched::with_status(200).body("b222")));
+        run.record("/c", "edge1", &Ok(Fetched::with_status(200).body("cccc")));
+        run.record("/c", "edge2", &Ok(Fetched::with_status(200).body("c222")));
+        run.record("/c", "edge3", &Ok(Fetched::with_status(200).body("cccc")));
+        run.record("/d", "direct", &Ok(Fetched::with_status(404).body("dddd")));
+        run.record("/d", "direct", &Err("reset".into()));
+
+        let drift = run.drift(Some(&previous));
+        assert_eq!(
+            drift.changed,
+            [(
+                "/b".to_string(),
+                vec!["bbbb".to_string()],
+                vec!["b222".to_string()]
+            )]
+        );
+        assert_eq!(drift.differing.len(), 1);
+        assert_eq!(drift.differing[0].1["cccc"], ["edge1", "edge3"]);
+        assert!(!run.urls.contains_key("/d"));
+        assert_eq!(run.drift(None).changed, []);
+
+        previous.update(&run);
+        assert_eq!(previous.urls.len(), 4);
+        assert!(previous.urls["/b"].contains_key("b222"));
+        assert!(previous.drift(Some(&previous)).changed.is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::fetch::{FetchError, Fetched};
+use crate::histogram::Histogram;
+use crate::stats::Stats;
+use reqwest::Url;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// What to break the totals down by.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GroupKey {
+    Host,
+    /// 2xx, 3xx, 4xx, 5xx, or "error" when there was no response.
+    StatusClass,
+    /// The media type, without parameters.
+    ContentType,
+}
+
+impl GroupKey {
+    pub const ALL: [GroupKey; 3] = [GroupKey::Host, GroupKey::StatusClass, GroupKey::ContentType];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GroupKey::Host => "host",
+            GroupKey::StatusClass => "status",
+            GroupKey::ContentType => "content-type",
+        }
+    }
+
+    fn value(&self, url: &str, result: &Result<Fetched, FetchError>) -> String {
+        match (self, result) {
+            (GroupKey::Host, _) => Url::parse(url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| "-".to_string()),
+            (GroupKey::StatusClass, Ok(fetched)) => {
+                format!("{}xx", fetched.status.as_u16() / 100)
+            }
+            (GroupKey::StatusClass, Err(_)) => "error".to_string(),
+            (GroupKey::ContentType, Ok(fetched)) => {
+                fetched.content_type().unwrap_or("-").to_string()
+            }
+            (GroupKey::ContentType, Err(_)) => "-".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for GroupKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for GroupKey {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        GroupKey::ALL
+            .into_iter()
+            .find(|key| key.name() == name)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unknown group key {:?}", name),
+                )
+            })
+    }
+}
+
+/// Totals for one group of requests.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Group {
+    pub stats: Stats,
+    pub requests: usize,
+    pub errors: usize,
+    latencies: Histogram,
+}
+
+impl Group {
+    fn record(&mut self, result: &Result<Fetched, FetchError>) {
+        self.requests += 1;
+        match result {
+            Ok(fetched) => {
+                self.stats.aggregate(&fetched.stats);
+                self.latencies.record(fetched.stats.elapsed_time);
+            }
+            Err(_) => self.errors += 1,
+        }
+    }
+
+    pub fn aggregate(&mut self, other: &Group) {
+        self.stats.aggregate(&other.stats);
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.latencies.aggregate(&other.latencies);
+    }
+
+    pub fn percentile(&self, pct: f64) -> Option<Duration> {
+        self.latencies.percentile(pct)
+    }
+}
+
+/// Totals broken down by each of a set of keys, e.g. per host and,
+/// separately, per status class.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GroupedStats {
+    keys: Vec<GroupKey>,
+    groups: BTreeMap<(GroupKey, String), Group>,
+}
+
+impl GroupedStats {
+    pub fn new(keys: &[GroupKey]) -> Self {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        keys.dedup();
+
+        GroupedStats {
+            keys,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    pub fn keys(&self) -> &[GroupKey] {
+        &self.keys
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    pub fn record(&mut self, url: &str, result: &Result<Fetched, FetchError>) {
+        for key in &self.keys {
+            self.groups
+                .entry((*key, key.value(url, result)))
+                .or_default()
+                .record(result);
+        }
+    }
+
+    /// Merge groups gathered elsewhere, taking on their keys as well.
+    pub fn aggregate(&mut self, other: &GroupedStats) {
+        for key in &other.keys {
+            if !self.keys.contains(key) {
+                self.keys.push(*key);
+            }
+        }
+        self.keys.sort();
+
+        for (group_key, group) in &other.groups {
+            self.groups
+                .entry(group_key.clone())
+                .or_default()
+                .aggregate(group);
+        }
+    }
+
+    /// The groups for `key`, by value.
+    pub fn by(&self, key: GroupKey) -> impl Iterator<Item = (&str, &Group)> {
+        self.groups
+            .iter()
+            .filter(move |((group_key, _), _)| *group_key == key)
+            .map(|((_, value), group)| (value.as_str(), group))
+    }
+}
+
+impl fmt::Display for GroupedStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for key in &self.keys {
+            writeln!(f, "by {}:", key)?;
+            for (value, group) in self.by(*key) {
+                let latency = |pct| match group.percentile(pct) {
+                    Some(latency) => format!("{:?}", latency),
+                    None => "-".to_string(),
+                };
+                writeln!(
+                    f,
+                    "  {:<30} {:>6} requests {:>4} errors  p50 {:<12} p99 {:<12} {:>12} bytes ({:.2} bytes/sec)",
+                    value,
+                    group.requests,
+                    group.errors,
+                    latency(50.0),
+                    latency(99.0),
+                    group.stats.content_length,
+                    group.stats.bytes_per_sec().unwrap_or_default()
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetch::Fetched;
+    use crate::groups::{GroupKey, GroupedStats};
+    use std::time::Duration;
+
+    const BODY: &str = "<p>hello</p>";
+
+    #[test]
+    fn test_grouped_stats() {
+        let page = Fetched::with_status(200)
+            .took(10)
+            .header("content-type", "text/html; charset=utf-8")
+            .body(BODY);
+        let missing = Fetched::with_status(404)
+            .took(30)
+            .header("content-type", "text/html")
+            .body(BODY);
+        let style = Fetched::with_status(200)
+            .took(20)
+            .header("content-type", "text/css")
+            .body(BODY);
+
+        let mut first = GroupedStats::new(&[GroupKey::Host, GroupKey::StatusClass]);
+        first.record("https://a.example.com/", &Ok(page));
+        first.record("https://a.example.com/x", &Ok(missing));
+        first.record("https://b.example.com/", &Err("timed out".into()));
+
+        let mut second = GroupedStats::new(&[GroupKey::ContentType]);
+        second.record("https://b.example.com/", &Ok(style));
+
+        let hosts: Vec<_> = first
+            .by(GroupKey::Host)
+            .map(|(host, group)| (host, group.requests, group.errors))
+            .collect();
+        assert_eq!(hosts, [("a.example.com", 2, 0), ("b.example.com", 1, 1)]);
+
+        let classes: Vec<_> = first.by(GroupKey::StatusClass).map(|(c, _)| c).collect();
+        assert_eq!(classes, ["2xx", "4xx", "error"]);
+
+        first.aggregate(&second);
+        assert_eq!(first.keys(), GroupKey::ALL);
+        let types: Vec<_> = first.by(GroupKey::ContentType).map(|(t, _)| t).collect();
+        assert_eq!(types, ["text/css"]);
+
+        let (_, host) = first.by(GroupKey::Host).next().unwrap();
+        assert_eq!(host.percentile(50.0), Some(Duration::from_millis(10)));
+        assert_eq!(host.stats.content_length, 2 * BODY.len());
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// values below this are counted exactly, larger ones in buckets this many
+// to each power of two, so within about 3% of what was recorded
+const SUB_BUCKETS: u64 = 32;
+
+/// Latencies counted in log-linear buckets, for percentiles over any
+/// number of requests in bounded space.
+///
+/// Percentiles are the lower bound of the bucket they fall in, clamped to
+/// the smallest and largest latency recorded.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Histogram {
+    // bucket index to count; at most a couple of thousand buckets
+    buckets: BTreeMap<u16, u64>,
+    count: u64,
+    min: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    pub fn record(&mut self, latency: Duration) {
+        *self.buckets.entry(bucket(latency)).or_default() += 1;
+        if self.count == 0 || latency < self.min {
+            self.min = latency;
+        }
+        self.max = self.max.max(latency);
+        self.count += 1;
+    }
+
+    pub fn aggregate(&mut self, other: &Histogram) {
+        if other.count == 0 {
+            return;
+        }
+        for (index, count) in &other.buckets {
+            *self.buckets.entry(*index).or_default() += count;
+        }
+        if self.count == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+    }
+
+    pub fn len(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Nearest-rank percentile, like [`crate::progress::percentile`].
+    pub fn percentile(&self, pct: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = ((pct / 100.0 * self.count as f64).ceil() as u64).clamp(1, self.count);
+        if rank == self.count {
+            return Some(self.max);
+        }
+        let mut seen = 0;
+        let index = self.buckets.iter().find_map(|(index, count)| {
+            seen += count;
+            (seen >= rank).then_some(*index)
+        })?;
+
+        Some(lower_bound(index).clamp(self.min, self.max))
+    }
+}
+
+fn bucket(latency: Duration) -> u16 {
+    let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+    if micros < SUB_BUCKETS {
+        return micros as u16;
+    }
+
+    // shifted down to SUB_BUCKETS..2 * SUB_BUCKETS
+    let shift = (63 - micros.leading_zeros()) as u64 - SUB_BUCKETS.trailing_zeros() as u64;
+    (shift * SUB_BUCKETS + (micros >> shift)) as u16
+}
+
+fn lower_bound(index: u16) -> Duration {
+    let index = index as u64;
+    if index < 2 * SUB_BUCKETS {
+        return Duration::from_micros(index);
+    }
+
+    let shift = index / SUB_BUCKETS - 1;
+    let micros = (index - shift * SUB_BUCKETS) << shift;
+    Duration::from_micros(micros)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::histogram::Histogram;
+    use std::time::Duration;
+
+    #[test]
+    fn test_histogram() {
+        let mut first = Histogram::default();
+        assert_eq!(first.percentile(50.0), None);
+        for millis in [10, 20, 30] {
+            first.record(Duration::from_millis(millis));
+        }
+        let mut second = Histogram::default();
+        for millis in 1..=1000 {
+            second.record(Duration::from_millis(millis));
+        }
+        second.record(Duration::MAX);
+
+        assert_eq!(first.percentile(0.0), Some(Duration::from_millis(10)));
+        assert_eq!(first.percentile(100.0), Some(Duration::from_millis(30)));
+        // within a bucket of the real 20ms
+        let p50 = first.percentile(50.0).unwrap();
+        assert!(p50 <= Duration::from_millis(20) && p50 > Duration::from_millis(19));
+
+        first.aggregate(&second);
+        assert_eq!(first.len(), 1004);
+        let p90 = first.percentile(90.0).unwrap();
+        assert!(p90 > Duration::from_millis(870) && p90 <= Duration::from_millis(900));
+        assert_eq!(first.percentile(100.0), Some(Duration::MAX));
+    }
+}
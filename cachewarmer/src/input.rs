@@ -0,0 +1,522 @@
+use crate::assertions::Assertion;
+use crate::fetch::Job;
+use crate::signals::{POLL_INTERVAL, Signals};
+use crate::template::{Csv, Template};
+use flate2::read::MultiGzDecoder;
+use regex::Regex;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// what the sitemap protocol assumes when a URL has no priority
+const SITEMAP_PRIORITY: f64 = 0.5;
+
+static SITEMAP_URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<url>(.*?)</url>").unwrap());
+static SITEMAP_LOC: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<loc>\s*(.*?)\s*</loc>").unwrap());
+static SITEMAP_PRIORITY_TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<priority>\s*(.*?)\s*</priority>").unwrap());
+
+/// Where a URL list is read from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Source {
+    /// `-`
+    Stdin,
+    File(PathBuf),
+    /// Anything starting with `http://` or `https://`.
+    Url(String),
+}
+
+impl FromStr for Source {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self, Error> {
+        Ok(if source == "-" {
+            Source::Stdin
+        } else if source.starts_with("http://") || source.starts_with("https://") {
+            Source::Url(source.to_string())
+        } else {
+            Source::File(source.into())
+        })
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Stdin => f.write_str("stdin"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Url(url) => f.write_str(url),
+        }
+    }
+}
+
+impl Source {
+    /// Read the list, decompressing it if it is gzip or zstd compressed.
+    pub fn open(&self) -> Result<Box<dyn BufRead + Send>, Error> {
+        let reader: Box<dyn Read + Send> = match self {
+            Source::Stdin => Box::new(std::io::stdin()),
+            Source::File(path) => Box::new(File::open(path)?),
+            Source::Url(url) => {
+                // however long the list takes to download; only connecting
+                // is limited
+                let response = reqwest::blocking::Client::builder()
+                    .timeout(None)
+                    .connect_timeout(CONNECT_TIMEOUT)
+                    .build()
+                    .and_then(|client| client.get(url).send())
+                    .and_then(|resp| resp.error_for_status())
+                    .map_err(|e| Error::other(format!("{}: {}", url, e)))?;
+                Box::new(Named {
+                    inner: response,
+                    name: url.clone(),
+                })
+            }
+        };
+
+        decompress(reader)
+    }
+
+    /// Like [`Source::open`], but at the end of a file wait for more to be
+    /// appended (like `tail -f`) until a stop is requested. Stdin and URLs
+    /// are read until they are closed.
+    pub fn follow(&self, signals: &Signals) -> Result<Box<dyn BufRead + Send>, Error> {
+        match self {
+            Source::File(path) => decompress(Box::new(Follow {
+                inner: File::open(path)?,
+                signals: signals.clone(),
+            })),
+            _ => self.open(),
+        }
+    }
+}
+
+fn decompress(reader: Box<dyn Read + Send>) -> Result<Box<dyn BufRead + Send>, Error> {
+    let mut reader = BufReader::new(reader);
+    let magic = reader.fill_buf()?;
+
+    Ok(if magic.starts_with(GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Names the source in read errors, which could come from anywhere in a
+/// long download.
+struct Named<R> {
+    inner: R,
+    name: String,
+}
+
+impl<R: Read> Read for Named<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.inner
+            .read(buf)
+            .map_err(|e| Error::new(e.kind(), format!("{}: {}", self.name, e)))
+    }
+}
+
+/// Never reaches the end of the file, only waits for it to grow.
+struct Follow<R> {
+    inner: R,
+    signals: Signals,
+}
+
+impl<R: Read> Read for Follow<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        loop {
+            let read = self.inner.read(buf)?;
+            if read > 0 || buf.is_empty() || self.signals.stop_requested() {
+                return Ok(read);
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+fn parse_priority(value: &str) -> Result<f64, Error> {
+    value
+        .parse()
+        .map_err(|_| invalid(format!("Invalid priority {:?}", value)))
+}
+
+/// A URL, or a [`Template`] for many, followed by assertions and
+/// optionally `priority=<number>`.
+fn parse_line(line: &str, csv: Option<&Arc<Csv>>) -> Result<(Template, Job), Error> {
+    let mut parts = line.split_whitespace();
+    let url = parts.next().unwrap_or_default();
+    let mut assertions = Vec::new();
+    let mut priority = 0.0;
+    for part in parts {
+        match part.strip_prefix("priority=") {
+            Some(value) => priority = parse_priority(value)?,
+            None => assertions.push(Assertion::parse(part)?),
+        }
+    }
+
+    let template = Template::parse(url, csv)?;
+    let job = Job {
+        index: 0,
+        url: url.to_string(),
+        assertions,
+        priority,
+    };
+
+    Ok((template, job))
+}
+
+fn expand_line(
+    line: &str,
+    csv: Option<&Arc<Csv>>,
+) -> Box<dyn Iterator<Item = Result<Job, Error>> + Send> {
+    match parse_line(line, csv) {
+        Ok((template, job)) => Box::new(
+            template
+                .expand()
+                .map(move |url| Ok(Job { url, ..job.clone() })),
+        ),
+        Err(e) => Box::new(std::iter::once(Err(Error::new(
+            e.kind(),
+            format!("{}: {}", line.trim(), e),
+        )))),
+    }
+}
+
+/// Parse a URL list, one job per line (or many for a template line),
+/// as the lines come in. `{column}` placeholders are filled from `csv`.
+pub fn jobs<R: BufRead>(
+    reader: R,
+    csv: Option<Arc<Csv>>,
+) -> impl Iterator<Item = Result<Job, Error>> {
+    reader
+        .lines()
+        .flat_map(move |line| match line {
+            Ok(line) => expand_line(&line, csv.as_ref()),
+            Err(e) => Box::new(std::iter::once(Err(e))),
+        })
+        .enumerate()
+        .map(|(index, job)| job.map(|job| Job { index, ..job }))
+}
+
+fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The URLs of an XML sitemap (not a sitemap index), with their priority.
+pub fn sitemap_jobs(xml: &str) -> Result<Vec<Job>, Error> {
+    let mut jobs = Vec::new();
+    for (index, entry) in SITEMAP_URL.captures_iter(xml).enumerate() {
+        let entry = &entry[1];
+        let url = SITEMAP_LOC
+            .captures(entry)
+            .ok_or_else(|| invalid(format!("Sitemap entry without <loc>: {}", entry.trim())))?;
+        let priority = match SITEMAP_PRIORITY_TAG.captures(entry) {
+            Some(priority) => parse_priority(&priority[1])?,
+            None => SITEMAP_PRIORITY,
+        };
+
+        jobs.push(Job {
+            index,
+            url: unescape_xml(&url[1]),
+            assertions: Vec::new(),
+            priority,
+        });
+    }
+
+    Ok(jobs)
+}
+
+fn is_sitemap<R: BufRead>(reader: &mut R) -> Result<bool, Error> {
+    Ok(reader.fill_buf()?.trim_ascii_start().starts_with(b"<"))
+}
+
+/// A whole URL list, or an XML sitemap, with its templates left
+/// unexpanded until the jobs are taken.
+///
+/// Jobs come highest priority first and the rest in list order, and are
+/// numbered by where they are in the list.
+#[derive(Clone, Debug, Default)]
+pub struct JobList {
+    // each job is numbered as the first URL of its line
+    lines: Arc<[(Template, Job)]>,
+    len: u64,
+}
+
+impl JobList {
+    pub fn read<R: BufRead>(mut reader: R, csv: Option<Arc<Csv>>) -> Result<Self, Error> {
+        if is_sitemap(&mut reader)? {
+            let mut xml = String::new();
+            reader.read_to_string(&mut xml)?;
+            return Ok(JobList::from_jobs(sitemap_jobs(&xml)?));
+        }
+
+        let mut lines = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            let parsed = parse_line(&line, csv.as_ref())
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", line.trim(), e)))?;
+            lines.push(parsed);
+        }
+
+        Ok(JobList::new(lines))
+    }
+
+    /// A list of plain URLs, such as an access log import.
+    pub fn from_jobs(jobs: Vec<Job>) -> Self {
+        let lines = jobs
+            .into_iter()
+            .map(|job| (Template::literal(&job.url), job))
+            .collect();
+
+        JobList::new(lines)
+    }
+
+    fn new(mut lines: Vec<(Template, Job)>) -> Self {
+        let mut len = 0u64;
+        for (template, job) in &mut lines {
+            job.index = len as usize;
+            len = len.saturating_add(template.count());
+        }
+        // stable, so lines of the same priority stay in list order
+        lines.sort_by(|(_, a), (_, b)| b.priority.total_cmp(&a.priority));
+
+        JobList {
+            lines: lines.into(),
+            len,
+        }
+    }
+
+    /// How many URLs the list has once its templates are expanded.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Every job, made as it is taken.
+    pub fn jobs(&self) -> impl Iterator<Item = Job> + Send + 'static {
+        let lines = self.lines.clone();
+        (0..lines.len()).flat_map(move |line| {
+            let (template, job) = lines[line].clone();
+            template.expand().enumerate().map(move |(offset, url)| Job {
+                index: job.index + offset,
+                url,
+                ..job.clone()
+            })
+        })
+    }
+
+    /// Every URL in list order, whatever the priorities.
+    pub fn urls(&self) -> impl Iterator<Item = String> + '_ {
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by_key(|(_, job)| job.index);
+        lines
+            .into_iter()
+            .flat_map(|(template, _)| template.clone().expand())
+    }
+}
+
+/// How many URLs a list has once its templates are expanded, and the
+/// first `sample` of them, without expanding it all.
+pub fn preview<R: BufRead>(
+    mut reader: R,
+    csv: Option<Arc<Csv>>,
+    sample: usize,
+) -> Result<(u64, Vec<String>), Error> {
+    if is_sitemap(&mut reader)? {
+        let list = JobList::read(reader, csv)?;
+        let urls = list.urls().take(sample);
+        return Ok((list.len(), urls.collect()));
+    }
+
+    let mut count = 0u64;
+    let mut urls = Vec::new();
+    for line in reader.lines() {
+        let (template, _) = parse_line(&line?, csv.as_ref())?;
+        count = count.saturating_add(template.count());
+        let missing = sample - urls.len();
+        urls.extend(template.expand().take(missing));
+    }
+
+    Ok((count, urls))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::{JobList, Source, jobs, preview};
+    use crate::template::Csv;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const LIST: &str = "https://example.com/a\nhttps://example.com/b status=200\n";
+
+    fn urls(source: &Source) -> Vec<String> {
+        jobs(source.open().unwrap(), None)
+            .map(|job| job.unwrap().url)
+            .collect()
+    }
+
+    #[test]
+    fn test_sources() {
+        assert_eq!("-".parse::<Source>().unwrap(), Source::Stdin);
+        assert_eq!(
+            "https://example.com/urls.txt".parse::<Source>().unwrap(),
+            Source::Url("https://example.com/urls.txt".to_string())
+        );
+        assert_eq!(
+            "urls.txt.gz".parse::<Source>().unwrap(),
+            Source::File(PathBuf::from("urls.txt.gz"))
+        );
+
+        let dir = std::env::temp_dir().join(format!("cachewarmer-input-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let plain = dir.join("urls.txt");
+        std::fs::write(&plain, LIST).unwrap();
+
+        let gzip = dir.join("urls.txt.gz");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(LIST.as_bytes()).unwrap();
+        std::fs::write(&gzip, encoder.finish().unwrap()).unwrap();
+
+        let zstd = dir.join("urls.txt.zst");
+        std::fs::write(&zstd, zstd::encode_all(LIST.as_bytes(), 0).unwrap()).unwrap();
+
+        for path in [plain, gzip, zstd] {
+            assert_eq!(
+                urls(&Source::File(path)),
+                ["https://example.com/a", "https://example.com/b"]
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // nothing listens on a port just given back
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let url = format!("http://127.0.0.1:{}/urls.txt", port);
+        let error = Source::Url(url.clone()).open().err().unwrap();
+        assert!(error.to_string().starts_with(&url), "{}", error);
+    }
+
+    #[test]
+    fn test_priorities() {
+        let list = "https://example.com/a status=200\nhttps://example.com/{b,c} priority=2\n";
+        let priorities: Vec<_> = JobList::read(list.as_bytes(), None)
+            .unwrap()
+            .jobs()
+            .map(|job| (job.index, job.url, job.assertions.len(), job.priority))
+            .collect();
+        assert_eq!(
+            priorities,
+            [
+                (1, "https://example.com/b".to_string(), 0, 2.0),
+                (2, "https://example.com/c".to_string(), 0, 2.0),
+                (0, "https://example.com/a".to_string(), 1, 0.0)
+            ]
+        );
+        let urls: Vec<_> = JobList::read(list.as_bytes(), None)
+            .unwrap()
+            .urls()
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c"
+            ]
+        );
+        assert!(JobList::read("https://example.com/ priority=high".as_bytes(), None).is_err());
+
+        let sitemap = r#"
+            <?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/?a=1&amp;b=2</loc><priority>0.9</priority></url>
+              <url>
+                <loc>
+                  https://example.com/c
+                </loc>
+              </url>
+            </urlset>"#;
+        let priorities: Vec<_> = JobList::read(sitemap.as_bytes(), None)
+            .unwrap()
+            .jobs()
+            .map(|job| (job.index, job.url, job.priority))
+            .collect();
+        assert_eq!(
+            priorities,
+            [
+                (0, "https://example.com/?a=1&b=2".to_string(), 0.9),
+                (1, "https://example.com/c".to_string(), 0.5)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_templates() {
+        let csv = Arc::new(Csv::parse("id\n7\n9\n").unwrap());
+        let list = "https://example.com/\nhttps://example.com/{id}?w={320,640} status=200\n";
+
+        let jobs: Vec<_> = jobs(list.as_bytes(), Some(csv.clone()))
+            .map(|job| job.unwrap())
+            .collect();
+        let urls: Vec<_> = jobs
+            .iter()
+            .map(|job| (job.index, job.url.as_str()))
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                (0, "https://example.com/"),
+                (1, "https://example.com/7?w=320"),
+                (2, "https://example.com/7?w=640"),
+                (3, "https://example.com/9?w=320"),
+                (4, "https://example.com/9?w=640"),
+            ]
+        );
+        assert_eq!(jobs[4].assertions.len(), 1);
+
+        let (count, sample) =
+            preview("https://example.com/{1..1000000}/{a,b}".as_bytes(), None, 3).unwrap();
+        assert_eq!(count, 2_000_000);
+        assert_eq!(
+            sample,
+            [
+                "https://example.com/1/a",
+                "https://example.com/1/b",
+                "https://example.com/2/a"
+            ]
+        );
+    }
+}
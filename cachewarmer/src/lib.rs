@@ -0,0 +1,37 @@
+//! Warm HTTP caches by fetching lists of URLs.
+//!
+//! [`Warmer`] is the entry point for using this from other programs; the
+//! remaining modules are the building blocks shared with the
+//! `cachewarmer` binary.
+
+pub mod accesslog;
+pub mod adaptive;
+pub mod assertions;
+pub mod auth;
+pub mod breaker;
+pub mod cacheability;
+pub mod checkpoint;
+pub mod compare;
+pub mod fetch;
+pub mod fingerprint;
+pub mod groups;
+pub mod histogram;
+pub mod input;
+pub mod normalize;
+pub mod origin;
+pub mod progress;
+pub mod proxy;
+pub mod redirect;
+pub mod report;
+pub mod run;
+pub mod signals;
+pub mod sink;
+pub mod stats;
+pub mod strategy;
+pub mod template;
+pub mod timing;
+pub mod tls;
+pub mod warmer;
+
+pub use stats::Stats;
+pub use warmer::{Warmer, WarmerBuilder};
@@ -1,3 +1,734 @@
-fn main() {
-    println!("Hello, world!");
+use cachewarmer::accesslog::{Cutoff, HostMapping, Import, ImportOptions};
+use cachewarmer::adaptive::AdaptiveConfig;
+use cachewarmer::auth::{Auth, Credentials, load_cookie_jar};
+use cachewarmer::breaker::BreakerConfig;
+use cachewarmer::checkpoint::Checkpoint;
+use cachewarmer::compare::RunResults;
+use cachewarmer::fetch::{Fetcher, Job};
+use cachewarmer::fingerprint::Fingerprints;
+use cachewarmer::groups::{GroupKey, GroupedStats};
+use cachewarmer::input::{self, JobList, Source};
+use cachewarmer::normalize::{DEFAULT_STRIP, Dedup, Normalizer};
+use cachewarmer::origin::{self, Origin, OriginReport};
+use cachewarmer::progress::{Progress, Reporter};
+use cachewarmer::proxy::{PoolMode, ProxyConfig, ProxyPool, load_proxies};
+use cachewarmer::redirect::{DEFAULT_MAX_HOPS, RedirectPolicy};
+use cachewarmer::report::{TestCase, save_junit, save_tap};
+use cachewarmer::run::Run;
+use cachewarmer::signals::Signals;
+use cachewarmer::sink::{ConsoleSink, CsvSink, JsonLinesSink, MetricsSink, ResultSink};
+use cachewarmer::stats::Stats;
+use cachewarmer::strategy::{DEFAULT_CONCURRENCY, Executor, Strategy};
+use cachewarmer::template::Csv;
+use cachewarmer::timing;
+use cachewarmer::tls::{self, CertReport, TlsConfig};
+use clap::{Parser, ValueEnum};
+use regex::Regex;
+use reqwest::Url;
+use reqwest::header::HeaderName;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum StrategyArg {
+    BlockOnSequential,
+    BlockOnThreadPerUrl,
+    BlockOnThreadChannel,
+    AsyncSequential,
+    FuturesUnordered,
+    BlockOnWorkerPool,
+    /// Every strategy in turn, for comparison
+    All,
+}
+
+impl StrategyArg {
+    fn strategies(&self) -> Vec<Strategy> {
+        match self {
+            StrategyArg::BlockOnSequential => vec![Strategy::BlockOnSequential],
+            StrategyArg::BlockOnThreadPerUrl => vec![Strategy::BlockOnThreadPerUrl],
+            StrategyArg::BlockOnThreadChannel => vec![Strategy::BlockOnThreadChannel],
+            StrategyArg::AsyncSequential => vec![Strategy::AsyncSequential],
+            StrategyArg::FuturesUnordered => vec![Strategy::FuturesUnordered],
+            StrategyArg::BlockOnWorkerPool => vec![Strategy::BlockOnWorkerPool],
+            StrategyArg::All => Strategy::ALL.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum RedirectArg {
+    Follow,
+    None,
+    SameHost,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum ProxyModeArg {
+    Every,
+    Rotate,
+}
+
+/// Warm HTTP caches by fetching every URL in a list.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// URL list: a file (optionally gzip or zstd compressed), an http(s)
+    /// URL, or - for stdin. One URL per line, each optionally followed by
+    /// assertions. A line may be a template for many URLs, with
+    /// placeholders like {1..5000}, {webp,avif} or {column}
+    urls: Source,
+
+    /// CSV file whose columns fill {column} placeholders in URL templates
+    #[arg(long)]
+    values: Option<PathBuf>,
+
+    /// Warm URLs as listed, without normalizing them and merging the
+    /// duplicates. Merging keeps a 16-byte digest of every URL for the
+    /// whole run, 20 to 40 MB per million URLs
+    #[arg(long)]
+    no_normalize: bool,
+
+    /// Query parameter to strip when normalizing, or a prefix ending in *;
+    /// may be repeated
+    #[arg(long = "strip-param", default_values = DEFAULT_STRIP, conflicts_with = "no_normalize")]
+    strip_params: Vec<String>,
+
+    /// Print how many URLs the list expands to and a sample of them,
+    /// without warming anything
+    #[arg(long, conflicts_with = "follow")]
+    dry_run: bool,
+
+    /// Keep reading the list as it grows and warm new URLs as they arrive
+    #[arg(long, conflicts_with = "checkpoint")]
+    follow: bool,
+
+    /// The list is an access log (combined format or JSON lines); warm
+    /// the URLs of its successful GET requests, most requested first
+    #[arg(long, conflicts_with = "follow")]
+    access_log: bool,
+
+    /// Warm only this many of the most requested URLs from the access log
+    #[arg(long, requires = "access_log", conflicts_with = "coverage")]
+    top: Option<usize>,
+
+    /// Warm the most requested URLs that got this percentage of all hits
+    #[arg(long, requires = "access_log")]
+    coverage: Option<f64>,
+
+    /// Import only access log paths matching this regex; may be repeated
+    #[arg(long, requires = "access_log")]
+    include: Vec<Regex>,
+
+    /// Skip access log paths matching this regex; may be repeated
+    #[arg(long, requires = "access_log")]
+    exclude: Vec<Regex>,
+
+    /// Base URL for access log lines that don't name a host
+    #[arg(long, requires = "access_log")]
+    base_url: Option<Url>,
+
+    /// Fetch the paths logged for a host from another base URL
+    /// (host=url); may be repeated
+    #[arg(long, requires = "access_log")]
+    map_host: Vec<HostMapping>,
+
+    /// How to run the requests; repeat to compare several strategies
+    #[arg(
+        short,
+        long = "strategy",
+        value_enum,
+        default_value = "futures-unordered"
+    )]
+    strategies: Vec<StrategyArg>,
+
+    /// Number of threads for the block-on-worker-pool strategy
+    #[arg(short = 'j', long, default_value_t = 8)]
+    workers: usize,
+
+    /// Requests in flight at once for the futures-unordered strategy and
+    /// --follow
+    #[arg(short, long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+
+    /// Which redirects to follow
+    #[arg(long, value_enum, default_value = "follow")]
+    redirects: RedirectArg,
+
+    /// Follow at most this many redirects per URL
+    #[arg(long, default_value_t = DEFAULT_MAX_HOPS)]
+    max_redirects: usize,
+
+    /// Also warm every redirect target as an entry of its own
+    #[arg(long)]
+    warm_redirect_targets: bool,
+
+    /// Adapt the number of requests in flight to each host: raise it while
+    /// latency stays stable, cut it back when latency or errors rise
+    #[arg(long)]
+    adaptive: bool,
+
+    /// Requests in flight to each host to start the adaptive limit at
+    #[arg(long, default_value_t = AdaptiveConfig::default().initial, requires = "adaptive")]
+    adaptive_initial: usize,
+
+    /// Never allow more than this many requests in flight to a host
+    #[arg(long, default_value_t = AdaptiveConfig::default().max, requires = "adaptive")]
+    adaptive_max: usize,
+
+    /// Stop sending requests to a host for a while when too many of them
+    /// fail, then probe it with a single request
+    #[arg(long)]
+    circuit_breaker: bool,
+
+    /// Percentage of failed recent requests that opens a host's breaker
+    #[arg(long, default_value_t = BreakerConfig::default().error_rate * 100.0, requires = "circuit_breaker")]
+    breaker_error_rate: f64,
+
+    /// Requests slower than this many milliseconds count as failed
+    #[arg(long, requires = "circuit_breaker")]
+    breaker_latency: Option<u64>,
+
+    /// How many recent requests to a host its breaker judges by
+    #[arg(long, default_value_t = BreakerConfig::default().window, requires = "circuit_breaker")]
+    breaker_window: usize,
+
+    /// Seconds an open breaker waits before letting a probe through
+    #[arg(long, default_value_t = BreakerConfig::default().cooldown.as_secs(), requires = "circuit_breaker")]
+    breaker_cooldown: u64,
+
+    /// Stop starting new requests after this many seconds; the highest
+    /// priority URLs are warmed first
+    #[arg(long)]
+    deadline: Option<u64>,
+
+    /// Periodically save progress to this file
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Skip URLs already done according to --checkpoint
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Print every URL as it completes, not only failures
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Break the totals down by these keys (host, status, content-type)
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "host,status,content-type"
+    )]
+    group_by: Vec<GroupKey>,
+
+    /// Write a JSON object per request to this file
+    #[arg(long)]
+    jsonl: Option<PathBuf>,
+
+    /// Write a CSV row per request to this file
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Write the totals to this file in the Prometheus text format
+    #[arg(long)]
+    metrics: Option<PathBuf>,
+
+    /// Save per-URL results and totals to this file, e.g. as a baseline
+    #[arg(long)]
+    save_results: Option<PathBuf>,
+
+    /// Compare the run with results saved by --save-results
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// Keep body hashes per URL in this file from run to run, and report
+    /// URLs whose content changed since the last one
+    #[arg(long)]
+    fingerprints: Option<PathBuf>,
+
+    /// Also fetch every URL from the origin, a base URL or the IP address
+    /// of the origin server, and report responses through the CDN that
+    /// differ from it
+    #[arg(long)]
+    origin: Option<Origin>,
+
+    /// Header to compare with the origin's; may be repeated
+    #[arg(
+        long = "compare-header",
+        default_values = origin::DEFAULT_HEADERS,
+        requires = "origin"
+    )]
+    compare_headers: Vec<HeaderName>,
+
+    /// Latency increase (in percent) over the baseline that counts as a regression
+    #[arg(long, default_value_t = 10.0, requires = "baseline")]
+    threshold: f64,
+
+    /// Write a JUnit XML report of the assertions
+    #[arg(long)]
+    junit: Option<PathBuf>,
+
+    /// Write a TAP report of the assertions
+    #[arg(long)]
+    tap: Option<PathBuf>,
+
+    /// JSON credentials file (otherwise CACHEWARMER_* environment variables are used)
+    #[arg(long)]
+    credentials: Option<PathBuf>,
+
+    /// Host (or host:port) to send the credentials to; may be repeated,
+    /// and is required with credentials
+    #[arg(long = "auth-host")]
+    auth_hosts: Vec<String>,
+
+    /// File with `<url> <Set-Cookie value>` lines to seed the cookie jar
+    #[arg(long)]
+    cookies: Option<PathBuf>,
+
+    /// Send requests through this proxy ([name=]url); may be repeated
+    #[arg(long = "proxy")]
+    proxies: Vec<ProxyConfig>,
+
+    /// File with one proxy ([name=]url) per line
+    #[arg(long = "proxies")]
+    proxies_file: Option<PathBuf>,
+
+    /// Send each URL through every proxy, or through the next one in turn
+    #[arg(long, value_enum, default_value = "every")]
+    proxy_mode: ProxyModeArg,
+
+    /// Extra CA certificates (PEM bundle) to trust; may be repeated
+    #[arg(long)]
+    ca_cert: Vec<PathBuf>,
+
+    /// Client certificate (PEM) for mutual TLS
+    #[arg(long)]
+    client_cert: Option<PathBuf>,
+
+    /// Private key (PEM) for --client-cert, if not in the same file
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+
+    /// DANGEROUS: do not verify TLS certificates
+    #[arg(long)]
+    insecure: bool,
+
+    /// Report certificates expiring within this many days
+    #[arg(long, default_value_t = 30)]
+    cert_expiry_days: u64,
+}
+
+// how many URLs --dry-run shows
+const DRY_RUN_SAMPLE: usize = 10;
+
+fn print_dry_run(count: u64, sample: &[String]) {
+    println!("{} urls", count);
+    for url in sample {
+        println!("  {}", url);
+    }
+    if count > sample.len() as u64 {
+        println!("  ...");
+    }
+}
+
+struct Summary {
+    strategy: Strategy,
+    requests: usize,
+    failed: usize,
+    totals: Stats,
+    wall_clock: Duration,
+}
+
+fn print_comparison(summaries: &[Summary]) {
+    println!(
+        "{:<24} {:>8} {:>7} {:>14} {:>12} {:>14} {:>14}",
+        "strategy", "requests", "failed", "request time", "bytes", "bytes/sec", "wall clock"
+    );
+
+    for summary in summaries {
+        println!(
+            "{:<24} {:>8} {:>7} {:>14.3?} {:>12} {:>14.2} {:>14.3?}",
+            summary.strategy.name(),
+            summary.requests,
+            summary.failed,
+            summary.totals.elapsed_time,
+            summary.totals.content_length,
+            summary.totals.bytes_per_sec().unwrap_or_default(),
+            summary.wall_clock,
+        );
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+
+    let mut strategies: Vec<Strategy> = Vec::new();
+    for strategy in args.strategies.iter().flat_map(StrategyArg::strategies) {
+        if !strategies.contains(&strategy) {
+            strategies.push(strategy);
+        }
+    }
+
+    if strategies.len() > 1 && args.checkpoint.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--checkpoint works with a single strategy",
+        )
+        .into());
+    }
+
+    if args.follow && strategies != [Strategy::FuturesUnordered] {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "--follow works with the futures-unordered strategy only",
+        )
+        .into());
+    }
+
+    let mut tls = TlsConfig::default();
+    for path in &args.ca_cert {
+        tls.add_ca_bundle(path)?;
+    }
+    if let Some(cert_path) = &args.client_cert {
+        tls.set_client_cert(cert_path, args.client_key.as_ref())?;
+    }
+    if args.insecure {
+        eprintln!("WARNING: --insecure disables TLS certificate verification");
+        tls.set_insecure(true);
+    }
+    let tls = tls.client_config(timing::crypto_provider())?;
+
+    let mut proxies = args.proxies.clone();
+    if let Some(path) = &args.proxies_file {
+        proxies.extend(load_proxies(path)?);
+    }
+    let proxy_mode = match args.proxy_mode {
+        ProxyModeArg::Every => PoolMode::Every,
+        ProxyModeArg::Rotate => PoolMode::Rotate,
+    };
+
+    let redirect_policy = match args.redirects {
+        RedirectArg::Follow => RedirectPolicy::Follow(args.max_redirects),
+        RedirectArg::None => RedirectPolicy::None,
+        RedirectArg::SameHost => RedirectPolicy::SameHost(args.max_redirects),
+    };
+
+    let credentials = match &args.credentials {
+        Some(path) => Some(Credentials::from_file(path)?),
+        None => Credentials::from_env()?,
+    };
+    if credentials.is_some() && args.auth_hosts.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "credentials need --auth-host for the hosts to send them to",
+        )
+        .into());
+    }
+    let auth =
+        credentials.map(|credentials| Arc::new(Auth::new(credentials, args.auth_hosts.clone())));
+
+    let cookie_jar = match &args.cookies {
+        Some(path) => Some(load_cookie_jar(path)?),
+        None => None,
+    };
+
+    let signals = Signals::install()?;
+    if let Some(deadline) = args.deadline {
+        let signals = signals.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(deadline));
+            println!("deadline of {}s reached, stopping", deadline);
+            signals.request_stop();
+        });
+    }
+    let values = match &args.values {
+        Some(path) => Some(Arc::new(Csv::load(path)?)),
+        None => None,
+    };
+    if args.dry_run && !args.access_log {
+        let (count, sample) = input::preview(args.urls.open()?, values, DRY_RUN_SAMPLE)?;
+        print_dry_run(count, &sample);
+        return Ok(());
+    }
+
+    let normalizer = (!args.no_normalize).then(|| Normalizer::new(args.strip_params.clone()));
+    // each strategy drops the duplicates of its own copy of the list
+    let dedup = || normalizer.clone().map(Dedup::new);
+    let mut merged = Arc::default();
+    let mut follow = None;
+    let list = if args.follow {
+        println!("Following urls from {}", args.urls);
+        let jobs = input::jobs(args.urls.follow(&signals)?, values);
+        let mut dedup = dedup();
+        merged = dedup.as_ref().map(Dedup::merged).unwrap_or_default();
+        follow = Some(jobs.filter_map(move |job| match (&mut dedup, job) {
+            (Some(dedup), Ok(job)) => dedup.check(job).map(Ok),
+            (_, job) => Some(job),
+        }));
+        JobList::default()
+    } else if args.access_log {
+        println!("Importing access log from {}", args.urls);
+        let mut import = Import::new(ImportOptions {
+            include: args.include.clone(),
+            exclude: args.exclude.clone(),
+            base_url: args.base_url.clone(),
+            hosts: args.map_host.clone(),
+            cutoff: match (args.top, args.coverage) {
+                (Some(top), _) => Cutoff::Top(top),
+                (_, Some(coverage)) => Cutoff::Coverage(coverage),
+                _ => Cutoff::All,
+            },
+        });
+        import.read(args.urls.open()?)?;
+        println!("Imported {}", import);
+        let jobs = import
+            .ranked()
+            .into_iter()
+            .map(|ranked| Job {
+                priority: ranked.hits as f64,
+                ..Job::from(ranked.url)
+            })
+            .collect();
+        JobList::from_jobs(jobs)
+    } else {
+        println!("Loading urls from {}", args.urls);
+        JobList::read(args.urls.open()?, values)?
+    };
+    if args.dry_run {
+        let mut dedup = dedup();
+        let sample: Vec<_> = list
+            .jobs()
+            .filter_map(|job| match &mut dedup {
+                Some(dedup) => dedup.check(job),
+                None => Some(job),
+            })
+            .take(DRY_RUN_SAMPLE)
+            .map(|job| job.url)
+            .collect();
+        print_dry_run(list.len(), &sample);
+        return Ok(());
+    }
+
+    let checkpoint = match &args.checkpoint {
+        Some(path) if args.resume => {
+            let checkpoint = Checkpoint::resume(path, list.urls())?;
+            println!("Resuming, {} urls already done", checkpoint.done_count());
+            checkpoint
+        }
+        Some(_) => Checkpoint::for_urls(list.urls()),
+        None => Checkpoint::new(list.len() as usize),
+    };
+
+    let mut sinks: Vec<Box<dyn ResultSink>> = vec![Box::new(ConsoleSink::new(args.verbose))];
+    if let Some(path) = &args.jsonl {
+        sinks.push(Box::new(JsonLinesSink::create(path)?));
+    }
+    if let Some(path) = &args.csv {
+        sinks.push(Box::new(CsvSink::create(path)?));
+    }
+    if let Some(path) = &args.metrics {
+        sinks.push(Box::new(MetricsSink::new(path)));
+    }
+
+    let baseline = match &args.baseline {
+        Some(path) => Some(RunResults::load(path)?),
+        None => None,
+    };
+
+    let previous_fingerprints = match &args.fingerprints {
+        Some(path) => Some(Fingerprints::load(path)?),
+        None => None,
+    };
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let mut executor = Executor::new(runtime.handle().clone(), signals.clone(), args.workers);
+    executor.set_concurrency(args.concurrency);
+    let certs = Arc::new(Mutex::new(CertReport::default()));
+    let mut summaries = Vec::new();
+    let mut cases = Vec::new();
+    let mut results = RunResults::default();
+    let mut fingerprints = Fingerprints::default();
+
+    for strategy in strategies.iter().copied() {
+        if signals.stop_requested() {
+            break;
+        }
+
+        if strategies.len() > 1 {
+            println!("== {}", strategy);
+        }
+
+        // a fresh connection pool for every strategy keeps the comparison fair
+        let client_builder = || {
+            // the fetcher follows redirects itself, to see every hop
+            let builder = tls::configure(timing::timed(reqwest::Client::builder()), &tls)
+                .redirect(reqwest::redirect::Policy::none());
+            match &cookie_jar {
+                Some(jar) => builder.cookie_provider(jar.clone()),
+                None => builder,
+            }
+        };
+        let pool = ProxyPool::new(&proxies, proxy_mode, client_builder)?;
+
+        let progress = Progress::new();
+        let fanout = pool.fanout();
+        if follow.is_none() {
+            progress
+                .set_total((list.len() as usize).saturating_sub(checkpoint.done_count()) * fanout);
+        }
+        let mut dedup = dedup();
+        if follow.is_none() {
+            merged = dedup.as_ref().map(Dedup::merged).unwrap_or_default();
+        }
+        let done = checkpoint.clone();
+        let skipped = progress.clone();
+        let jobs = list
+            .jobs()
+            .filter_map(move |job| match &mut dedup {
+                Some(dedup) => {
+                    let job = dedup.check(job);
+                    if job.is_none() {
+                        skipped.reduce_total(fanout);
+                    }
+                    job
+                }
+                None => Some(job),
+            })
+            .filter(move |job| !done.is_done(job.index));
+
+        let mut fetcher = Fetcher::new(pool, auth.clone(), certs.clone(), progress.clone());
+        fetcher.set_redirects(redirect_policy, args.warm_redirect_targets);
+        if args.adaptive {
+            fetcher.set_adaptive(AdaptiveConfig {
+                initial: args.adaptive_initial,
+                max: args.adaptive_max,
+                ..AdaptiveConfig::default()
+            });
+        }
+        if args.circuit_breaker {
+            fetcher.set_breaker(BreakerConfig {
+                error_rate: args.breaker_error_rate / 100.0,
+                max_latency: args.breaker_latency.map(Duration::from_millis),
+                window: args.breaker_window,
+                cooldown: Duration::from_secs(args.breaker_cooldown),
+            });
+        }
+        if let Some(origin) = &args.origin {
+            let client = origin.client(client_builder()).build()?;
+            fetcher.set_origin(client, origin.clone());
+        }
+        let fetcher = Arc::new(fetcher);
+        let mut run = Run::new(checkpoint.clone(), args.checkpoint.clone());
+        run.groups = GroupedStats::new(&args.group_by);
+        run.origin = OriginReport::new(&args.compare_headers);
+        for sink in sinks.drain(..) {
+            run.add_sink(sink);
+        }
+        let run = Arc::new(Mutex::new(run));
+
+        let start = Instant::now();
+        let reporter = Reporter::spawn(progress.clone());
+        match follow.take() {
+            Some(jobs) => executor.follow(fetcher.clone(), jobs, &run)?,
+            None => executor.execute(strategy, fetcher.clone(), jobs, run.clone())?,
+        }
+        reporter.finish();
+        let wall_clock = start.elapsed();
+
+        let mut run = run.lock().unwrap();
+        run.save_checkpoint()?;
+
+        if signals.stop_requested() {
+            println!(
+                "interrupted, abandoned {} requests in flight",
+                progress.snapshot().in_flight
+            );
+        }
+
+        run.merged = merged.load(Ordering::Relaxed);
+        run.breakers = fetcher.breaker_report();
+        run.concurrency = fetcher.concurrency_report();
+        run.finish()?;
+        sinks = run.take_sinks();
+        println!("wall clock time: {:?}", wall_clock);
+
+        summaries.push(Summary {
+            strategy,
+            requests: run.cases.len(),
+            failed: run.failed(),
+            totals: run.totals().clone(),
+            wall_clock,
+        });
+
+        results.merge(&run.results);
+        fingerprints.merge(&run.fingerprints);
+        cases.extend(run.cases.drain(..).map(|case| match strategies.len() {
+            1 => case,
+            _ => TestCase {
+                url: format!("[{}] {}", strategy, case.url),
+                ..case
+            },
+        }));
+    }
+
+    let certs = certs.lock().unwrap();
+    if !certs.is_empty() {
+        let expiring = certs.expiring_within(args.cert_expiry_days, SystemTime::now());
+        println!(
+            "{} certificates expiring within {} days",
+            expiring.len(),
+            args.cert_expiry_days
+        );
+        for (host, cert) in expiring {
+            println!("  {} {}", host, cert);
+        }
+    }
+
+    if summaries.len() > 1 {
+        print_comparison(&summaries);
+    }
+
+    let drift = fingerprints.drift(previous_fingerprints.as_ref());
+    if !drift.is_empty() {
+        print!("{}", drift);
+    }
+    if let (Some(path), Some(mut previous)) = (&args.fingerprints, previous_fingerprints) {
+        previous.update(&fingerprints);
+        previous.save(path)?;
+    }
+
+    if let Some(path) = &args.save_results {
+        results.save(path)?;
+    }
+
+    let mut regressions = 0;
+    if let Some(baseline) = &baseline {
+        let comparison = results.compare(baseline, args.threshold);
+        print!("{}", comparison);
+        regressions = comparison.regressions().count();
+    }
+
+    if let Some(path) = &args.junit {
+        save_junit(path, &cases)?;
+    }
+
+    if let Some(path) = &args.tap {
+        save_tap(path, &cases)?;
+    }
+
+    let failed = cases.iter().filter(|case| !case.passed()).count();
+    if failed > 0 {
+        return Err(Error::other(format!("{} of {} urls failed", failed, cases.len())).into());
+    }
+
+    if regressions > 0 {
+        return Err(
+            Error::other(format!("{} regressions against the baseline", regressions)).into(),
+        );
+    }
+
+    Ok(())
 }
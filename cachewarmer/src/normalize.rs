@@ -0,0 +1,175 @@
+use crate::fetch::Job;
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Tracking parameters stripped unless others are configured.
+pub const DEFAULT_STRIP: [&str; 3] = ["utm_*", "gclid", "fbclid"];
+
+/// Rewrites URLs that only differ in ways caches shouldn't care about
+/// into one form: lowercase scheme and host, no default port, no
+/// fragment, query parameters sorted and tracking parameters removed.
+#[derive(Clone, Debug)]
+pub struct Normalizer {
+    // parameter names, or prefixes when ending in *
+    strip: Vec<String>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::new(DEFAULT_STRIP)
+    }
+}
+
+impl Normalizer {
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(strip: I) -> Self {
+        Normalizer {
+            strip: strip.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    fn strips(&self, name: &str) -> bool {
+        self.strip
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// URLs that don't parse are left for the fetch to report.
+    pub fn normalize(&self, url: &str) -> String {
+        // parsing already takes care of the case and the default port
+        let Ok(mut url) = Url::parse(url) else {
+            return url.to_string();
+        };
+        url.set_fragment(None);
+
+        // the parameters are kept encoded as they were, only reordered
+        let mut params: Vec<_> = url
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .filter(|param| !self.strips(param.split('=').next().unwrap_or_default()))
+            .map(str::to_string)
+            .collect();
+        params.sort();
+        let query = params.join("&");
+        url.set_query(Some(query.as_str()).filter(|query| !query.is_empty()));
+
+        url.into()
+    }
+}
+
+/// Normalizes job URLs and keeps the first of each.
+///
+/// Later copies are dropped with their assertions; taking the jobs in
+/// priority order keeps the copy with the highest priority. A 16-byte
+/// digest of every URL kept is held on to, not the URL itself.
+#[derive(Debug, Default)]
+pub struct Dedup {
+    normalizer: Normalizer,
+    // the first half of the SHA-256 of each normalized URL kept; at 128
+    // bits a false match is out of the question for any list
+    seen: HashSet<[u8; 16]>,
+    merged: Arc<AtomicUsize>,
+}
+
+impl Dedup {
+    pub fn new(normalizer: Normalizer) -> Self {
+        Dedup {
+            normalizer,
+            ..Default::default()
+        }
+    }
+
+    /// How many jobs were duplicates, shared so it can be read while the
+    /// jobs are still coming in.
+    pub fn merged(&self) -> Arc<AtomicUsize> {
+        self.merged.clone()
+    }
+
+    /// The job with its URL normalized, or none if an earlier job had the
+    /// same URL.
+    pub fn check(&mut self, mut job: Job) -> Option<Job> {
+        job.url = self.normalizer.normalize(&job.url);
+        let digest = Sha256::digest(&job.url);
+        if !self.seen.insert(digest[..16].try_into().unwrap()) {
+            self.merged.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        Some(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetch::Job;
+    use crate::normalize::{Dedup, Normalizer};
+    use std::sync::atomic::Ordering;
+
+    fn job(index: usize, url: &str, priority: f64) -> Job {
+        Job {
+            index,
+            url: url.to_string(),
+            assertions: Vec::new(),
+            priority,
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalizer.normalize("HTTPS://WWW.Example.com:443/A?b=2&a=1&utm_source=x#top"),
+            "https://www.example.com/A?a=1&b=2"
+        );
+        assert_eq!(
+            normalizer.normalize("http://example.com:80/?utm_medium=y&gclid=z"),
+            "http://example.com/"
+        );
+        assert_eq!(
+            normalizer.normalize("http://example.com:8080/?q=a%20b&q=a"),
+            "http://example.com:8080/?q=a&q=a%20b"
+        );
+        assert_eq!(normalizer.normalize("not a url"), "not a url");
+        assert_eq!(
+            Normalizer::new(["ref"]).normalize("https://example.com/?utm_id=1&ref=2"),
+            "https://example.com/?utm_id=1"
+        );
+
+        let mut dedup = Dedup::new(normalizer);
+        let merged = dedup.merged();
+        let jobs: Vec<_> = [
+            job(0, "https://example.com/?b=2&a=1", 3.0),
+            job(1, "https://example.com/other", 0.0),
+            job(2, "https://EXAMPLE.com/?a=1&b=2&utm_campaign=c", 1.0),
+        ]
+        .into_iter()
+        .filter_map(|job| dedup.check(job))
+        .map(|job| (job.index, job.url, job.priority))
+        .collect();
+        assert_eq!(
+            jobs,
+            [
+                (0, "https://example.com/?a=1&b=2".to_string(), 3.0),
+                (1, "https://example.com/other".to_string(), 0.0)
+            ]
+        );
+        assert!(
+            dedup
+                .check(job(3, "https://example.com/other#x", 0.0))
+                .is_none()
+        );
+        assert!(
+            dedup
+                .check(job(4, "https://example.com/new", 0.0))
+                .is_some()
+        );
+        assert_eq!(merged.load(Ordering::Relaxed), 2);
+    }
+}
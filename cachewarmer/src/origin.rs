@@ -0,0 +1,271 @@
+use crate::accesslog;
+use crate::fetch::{FetchError, Fetched};
+use crate::fingerprint::short;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::HeaderName;
+use reqwest::{ClientBuilder, Url};
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Headers compared unless others are given: those that tell a stale or
+/// rewritten object apart.
+pub const DEFAULT_HEADERS: [&str; 4] = ["content-type", "location", "etag", "last-modified"];
+
+/// Where to fetch each URL from the origin, bypassing the CDN.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Origin {
+    /// The path and query of each URL appended to this base.
+    Base(Url),
+    /// The same URL, connecting to this address whatever the host.
+    Address(IpAddr),
+}
+
+impl FromStr for Origin {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Error> {
+        if let Ok(ip) = spec.parse() {
+            return Ok(Origin::Address(ip));
+        }
+
+        spec.parse().map(Origin::Base).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Origin {:?} is neither a URL nor an IP address: {}",
+                    spec, e
+                ),
+            )
+        })
+    }
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Base(base) => write!(f, "{}", base),
+            Origin::Address(ip) => write!(f, "{}", ip),
+        }
+    }
+}
+
+impl Origin {
+    /// Where the origin serves `url`.
+    pub fn url(&self, url: &str) -> String {
+        let Origin::Base(base) = self else {
+            return url.to_string();
+        };
+        let Ok(url) = Url::parse(url) else {
+            return url.to_string();
+        };
+
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        accesslog::join(base, &path)
+    }
+
+    /// Set up a client builder to reach the origin.
+    pub fn client(&self, builder: ClientBuilder) -> ClientBuilder {
+        match self {
+            Origin::Base(_) => builder,
+            Origin::Address(ip) => builder.dns_resolver(Arc::new(FixedResolver(*ip))),
+        }
+    }
+}
+
+// every name resolves to the origin; the connector fills in the port
+struct FixedResolver(IpAddr);
+
+impl Resolve for FixedResolver {
+    fn resolve(&self, _name: Name) -> Resolving {
+        let addr = SocketAddr::new(self.0, 0);
+        Box::pin(async move { Ok(Box::new(std::iter::once(addr)) as Addrs) })
+    }
+}
+
+/// A response that differs from the origin's.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mismatch {
+    pub url: String,
+    /// The proxy (or direct) the response came through.
+    pub edge: String,
+    pub differences: Vec<String>,
+}
+
+/// Responses compared with the origin, and those that differed.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OriginReport {
+    headers: Vec<HeaderName>,
+    pub compared: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl OriginReport {
+    pub fn new(headers: &[HeaderName]) -> Self {
+        OriginReport {
+            headers: headers.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    /// What differs between a response through the CDN and the origin's.
+    pub fn differences(&self, edge: &Fetched, origin: &Fetched) -> Vec<String> {
+        let mut differences = Vec::new();
+        if edge.status != origin.status {
+            differences.push(format!(
+                "status {}, origin {}",
+                edge.status.as_u16(),
+                origin.status.as_u16()
+            ));
+        }
+
+        for name in &self.headers {
+            let value = |fetched: &Fetched| {
+                fetched
+                    .headers
+                    .get(name)
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+            };
+            let (ours, theirs) = (value(edge), value(origin));
+            if ours != theirs {
+                differences.push(format!("header {} {:?}, origin {:?}", name, ours, theirs));
+            }
+        }
+
+        let (ours, theirs) = (edge.stats.content_length, origin.stats.content_length);
+        if ours != theirs {
+            differences.push(format!("body length {}, origin {}", ours, theirs));
+        } else if edge.sha256 != origin.sha256 {
+            differences.push(format!(
+                "body sha256 {}, origin {}",
+                short(&edge.sha256),
+                short(&origin.sha256)
+            ));
+        }
+
+        differences
+    }
+
+    /// Compare a response through `edge` with the origin's; requests that
+    /// failed through the CDN are failures already and aren't compared.
+    pub fn record(
+        &mut self,
+        url: &str,
+        edge: &str,
+        result: &Result<Fetched, FetchError>,
+        origin: &Result<Fetched, FetchError>,
+    ) {
+        let Ok(fetched) = result else {
+            return;
+        };
+
+        self.compared += 1;
+        let differences = match origin {
+            Ok(origin) => self.differences(fetched, origin),
+            Err(e) => vec![format!("origin request failed: {}", e)],
+        };
+        if !differences.is_empty() {
+            self.mismatches.push(Mismatch {
+                url: url.to_string(),
+                edge: edge.to_string(),
+                differences,
+            });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compared == 0
+    }
+}
+
+impl fmt::Display for OriginReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "origin comparison: {} of {} responses differ",
+            self.mismatches.len(),
+            self.compared
+        )?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {} via {}:", mismatch.url, mismatch.edge)?;
+            for difference in &mismatch.differences {
+                writeln!(f, "    {}", difference)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fetch::Fetched;
+    use crate::origin::{DEFAULT_HEADERS, Origin, OriginReport};
+    use reqwest::header::HeaderName;
+
+    #[test]
+    fn test_origin() {
+        let base: Origin = "http://origin.internal:8080/site".parse().unwrap();
+        assert_eq!(
+            base.url("https://www.example.com/a/b?c=d"),
+            "http://origin.internal:8080/site/a/b?c=d"
+        );
+        let address: Origin = "10.0.0.7".parse().unwrap();
+        assert_eq!(address, Origin::Address([10, 0, 0, 7].into()));
+        assert_eq!(
+            address.url("https://www.example.com/a"),
+            "https://www.example.com/a"
+        );
+        assert!("not an origin".parse::<Origin>().is_err());
+
+        let headers: Vec<HeaderName> = DEFAULT_HEADERS.iter().map(|h| h.parse().unwrap()).collect();
+        let mut report = OriginReport::new(&headers);
+        // x-cache differs from the origin's, but isn't compared
+        let current = Fetched::with_status(200)
+            .header("etag", "\"v2\"")
+            .header("x-cache", "HIT")
+            .body("new body");
+        let stale = Fetched::with_status(200)
+            .header("etag", "\"v1\"")
+            .body("old body");
+        let gone = Fetched::with_status(404)
+            .header("etag", "\"v2\"")
+            .body("new body");
+        let origin = Ok(Fetched::with_status(200)
+            .header("etag", "\"v2\"")
+            .body("new body"));
+        report.record("/same", "direct", &Ok(current.clone()), &origin);
+        report.record("/stale", "direct", &Ok(stale), &origin);
+        report.record("/short", "edge1", &Ok(current.clone().body("new")), &origin);
+        report.record("/gone", "direct", &Ok(gone), &origin);
+        report.record("/down", "direct", &Ok(current), &Err("timeout".into()));
+        report.record("/failed", "direct", &Err("reset".into()), &origin);
+
+        assert_eq!(report.compared, 5);
+        let mismatches: Vec<_> = report
+            .mismatches
+            .iter()
+            .map(|mismatch| (mismatch.url.as_str(), mismatch.differences.clone()))
+            .collect();
+        assert_eq!(
+            mismatches,
+            [
+                (
+                    "/stale",
+                    vec![
+                        r#"header etag Some("\"v1\""), origin Some("\"v2\"")"#.to_string(),
+                        "body sha256 old body, origin new body".to_string()
+                    ]
+                ),
+                ("/short", vec!["body length 3, origin 8".to_string()]),
+                ("/gone", vec!["status 404, origin 200".to_string()]),
+                ("/down", vec!["origin request failed: timeout".to_string()]),
+            ]
+        );
+    }
+}
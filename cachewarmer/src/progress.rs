@@ -0,0 +1,309 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{IsTerminal, Write};
+use std::sync::mpsc::{RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// how many recent latencies feed the percentiles
+const WINDOW_SIZE: usize = 1000;
+// how many seconds of completions the throughput is taken over
+const WINDOW_SPAN: u64 = 10;
+
+const TTY_INTERVAL: Duration = Duration::from_millis(200);
+const LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct State {
+    total: Option<usize>,
+    completed: usize,
+    failed: usize,
+    in_flight: usize,
+    // latencies of the most recent requests
+    recent: VecDeque<Duration>,
+    // (second since the start, completions in it) over the span
+    per_second: VecDeque<(u64, usize)>,
+}
+
+/// Live counters shared between the workers and the progress reporter.
+#[derive(Debug)]
+pub struct Progress {
+    start: Instant,
+    state: Mutex<State>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub elapsed: Duration,
+    pub total: Option<usize>,
+    pub completed: usize,
+    pub failed: usize,
+    pub in_flight: usize,
+    pub requests_per_sec: f64,
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub eta: Option<Duration>,
+}
+
+impl Progress {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Progress {
+            start: Instant::now(),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.state.lock().unwrap().total = Some(total);
+    }
+
+    /// Take requests off the total, e.g. for jobs found to be duplicates.
+    pub fn reduce_total(&self, requests: usize) {
+        if let Some(total) = &mut self.state.lock().unwrap().total {
+            *total = total.saturating_sub(requests);
+        }
+    }
+
+    pub fn started(&self) {
+        self.state.lock().unwrap().in_flight += 1;
+    }
+
+    pub fn completed(&self, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        state.completed += 1;
+        if state.recent.len() == WINDOW_SIZE {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(latency);
+
+        let second = self.start.elapsed().as_secs();
+        match state.per_second.back_mut() {
+            Some((last, count)) if *last == second => *count += 1,
+            _ => state.per_second.push_back((second, 1)),
+        }
+        while state
+            .per_second
+            .front()
+            .is_some_and(|(first, _)| first + WINDOW_SPAN <= second)
+        {
+            state.per_second.pop_front();
+        }
+    }
+
+    pub fn failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.in_flight = state.in_flight.saturating_sub(1);
+        state.failed += 1;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap();
+
+        let mut latencies: Vec<_> = state.recent.iter().copied().collect();
+        latencies.sort();
+
+        // the current second so far and the whole ones before it
+        let elapsed = now.duration_since(self.start);
+        let first = elapsed.as_secs().saturating_sub(WINDOW_SPAN - 1);
+        let in_window: usize = state
+            .per_second
+            .iter()
+            .filter(|(second, _)| *second >= first)
+            .map(|(_, count)| count)
+            .sum();
+        let window = (elapsed - Duration::from_secs(first)).as_secs_f64();
+        let requests_per_sec = if window < 0.001 {
+            0.0
+        } else {
+            in_window as f64 / window
+        };
+
+        let eta = state.total.and_then(|total| {
+            let remaining = total.saturating_sub(state.completed + state.failed);
+            if remaining == 0 {
+                Some(Duration::ZERO)
+            } else if requests_per_sec > 0.0 {
+                Some(Duration::from_secs_f64(remaining as f64 / requests_per_sec))
+            } else {
+                None
+            }
+        });
+
+        Snapshot {
+            elapsed,
+            total: state.total,
+            completed: state.completed,
+            failed: state.failed,
+            in_flight: state.in_flight,
+            requests_per_sec,
+            p50: percentile(&latencies, 50.0),
+            p90: percentile(&latencies, 90.0),
+            p99: percentile(&latencies, 99.0),
+            eta,
+        }
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice.
+pub fn percentile(sorted: &[Duration], pct: f64) -> Option<Duration> {
+    if sorted.is_empty() {
+        return None;
+    }
+
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+fn fmt_latency(latency: Option<Duration>) -> String {
+    match latency {
+        Some(latency) => format!("{}ms", latency.as_millis()),
+        None => "-".to_string(),
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.total {
+            Some(total) => write!(f, "{}/{} done", self.completed, total)?,
+            None => write!(f, "{} done", self.completed)?,
+        }
+
+        write!(
+            f,
+            ", {} failed, {} in flight, {:.1} req/s, p50 {} p90 {} p99 {}",
+            self.failed,
+            self.in_flight,
+            self.requests_per_sec,
+            fmt_latency(self.p50),
+            fmt_latency(self.p90),
+            fmt_latency(self.p99),
+        )?;
+
+        match self.eta {
+            Some(eta) => write!(f, ", ETA {}s", eta.as_secs()),
+            None => write!(f, ", ETA -"),
+        }
+    }
+}
+
+/// Background thread redrawing the progress line on stderr.
+///
+/// On a terminal the line is redrawn in place a few times a second,
+/// otherwise a plain log line is written every few seconds.
+pub struct Reporter {
+    stop: Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+impl Reporter {
+    pub fn spawn(progress: Arc<Progress>) -> Self {
+        let tty = std::io::stderr().is_terminal();
+        let interval = if tty { TTY_INTERVAL } else { LOG_INTERVAL };
+        let (stop, stopped) = std::sync::mpsc::channel();
+
+        let thread = std::thread::spawn(move || {
+            loop {
+                let done = !matches!(
+                    stopped.recv_timeout(interval),
+                    Err(RecvTimeoutError::Timeout)
+                );
+
+                let snapshot = progress.snapshot();
+                let mut stderr = std::io::stderr().lock();
+                if tty {
+                    let _ = write!(stderr, "\r\x1b[2K{}", snapshot);
+                    if done {
+                        let _ = writeln!(stderr);
+                    }
+                    let _ = stderr.flush();
+                } else {
+                    let _ = writeln!(stderr, "progress: {}", snapshot);
+                }
+
+                if done {
+                    break;
+                }
+            }
+        });
+
+        Reporter { stop, thread }
+    }
+
+    pub fn finish(self) {
+        let _ = self.stop.send(());
+        self.thread.join().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::progress::{Progress, percentile};
+    use std::time::Duration;
+
+    #[test]
+    fn test_percentile() {
+        let latencies: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+
+        assert_eq!(
+            percentile(&latencies, 50.0),
+            Some(Duration::from_millis(50))
+        );
+        assert_eq!(
+            percentile(&latencies, 99.0),
+            Some(Duration::from_millis(99))
+        );
+        assert_eq!(
+            percentile(&latencies, 100.0),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(percentile(&[], 50.0), None);
+    }
+
+    #[test]
+    fn test_progress_counters() {
+        let progress = Progress::new();
+        progress.set_total(3);
+        progress.started();
+        progress.started();
+        progress.completed(Duration::from_millis(10));
+        progress.started();
+        progress.failed();
+
+        let snapshot = progress.snapshot();
+        assert_eq!(snapshot.completed, 1);
+        assert_eq!(snapshot.failed, 1);
+        assert_eq!(snapshot.in_flight, 1);
+        assert_eq!(snapshot.p50, Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn test_progress_rate() {
+        let progress = Progress::new();
+        progress.set_total(10_000);
+        std::thread::sleep(Duration::from_millis(50));
+        for _ in 0..5000 {
+            progress.started();
+            progress.completed(Duration::from_millis(1));
+        }
+
+        // far more than the latencies kept, all within the span
+        let snapshot = progress.snapshot();
+        let expected = 5000.0 / snapshot.elapsed.as_secs_f64();
+        assert!(
+            (snapshot.requests_per_sec - expected).abs() < expected * 0.01,
+            "{} req/s, expected {}",
+            snapshot.requests_per_sec,
+            expected
+        );
+        let eta = snapshot.eta.unwrap();
+        assert!(
+            eta <= snapshot.elapsed + Duration::from_millis(1),
+            "{:?}",
+            eta
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use reqwest::header::LOCATION;
+use reqwest::{Response, StatusCode, Url};
+use serde::Serialize;
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How many redirects reqwest follows by default.
+pub const DEFAULT_MAX_HOPS: usize = 10;
+
+/// Which redirects to follow. The clients themselves must not follow any
+/// (`redirect::Policy::none()`), so that every hop can be recorded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RedirectPolicy {
+    /// Stop at the first redirect response.
+    None,
+    /// Follow up to this many redirects.
+    Follow(usize),
+    /// Follow up to this many redirects, but only within the same host.
+    SameHost(usize),
+}
+
+impl Default for RedirectPolicy {
+    fn default() -> Self {
+        RedirectPolicy::Follow(DEFAULT_MAX_HOPS)
+    }
+}
+
+impl RedirectPolicy {
+    pub fn with_max_hops(self, max_hops: usize) -> Self {
+        match self {
+            RedirectPolicy::None => RedirectPolicy::None,
+            RedirectPolicy::Follow(_) => RedirectPolicy::Follow(max_hops),
+            RedirectPolicy::SameHost(_) => RedirectPolicy::SameHost(max_hops),
+        }
+    }
+
+    /// The longest chain of redirects to follow or, when none are, to
+    /// warm as targets of their own.
+    pub fn max_hops(&self) -> usize {
+        match *self {
+            RedirectPolicy::None => DEFAULT_MAX_HOPS,
+            RedirectPolicy::Follow(max_hops) | RedirectPolicy::SameHost(max_hops) => max_hops,
+        }
+    }
+
+    /// Whether to follow a redirect from `from` to `to`, after `hops`
+    /// redirects were followed already.
+    pub fn allows(&self, hops: usize, from: &Url, to: &Url) -> bool {
+        match *self {
+            RedirectPolicy::None => false,
+            RedirectPolicy::Follow(max_hops) => hops < max_hops,
+            RedirectPolicy::SameHost(max_hops) => {
+                hops < max_hops
+                    && from.host_str() == to.host_str()
+                    && from.port_or_known_default() == to.port_or_known_default()
+            }
+        }
+    }
+}
+
+impl FromStr for RedirectPolicy {
+    type Err = Error;
+
+    fn from_str(policy: &str) -> Result<Self, Error> {
+        match policy {
+            "none" => Ok(RedirectPolicy::None),
+            "follow" => Ok(RedirectPolicy::Follow(DEFAULT_MAX_HOPS)),
+            "same-host" => Ok(RedirectPolicy::SameHost(DEFAULT_MAX_HOPS)),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown redirect policy {:?}", policy),
+            )),
+        }
+    }
+}
+
+/// The redirect target of a response, if it is a redirect at all.
+pub fn location(resp: &Response) -> Option<Url> {
+    if !matches!(
+        resp.status(),
+        StatusCode::MOVED_PERMANENTLY
+            | StatusCode::FOUND
+            | StatusCode::SEE_OTHER
+            | StatusCode::TEMPORARY_REDIRECT
+            | StatusCode::PERMANENT_REDIRECT
+    ) {
+        return None;
+    }
+
+    let location = resp.headers().get(LOCATION)?.to_str().ok()?;
+    resp.url().join(location).ok()
+}
+
+/// One redirect on the way to the final response.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Hop {
+    pub url: String,
+    pub status: u16,
+    pub elapsed_time: Duration,
+    pub location: String,
+}
+
+impl fmt::Display for Hop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} -> {} ({:?})",
+            self.url, self.status, self.location, self.elapsed_time
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::redirect::RedirectPolicy;
+    use reqwest::Url;
+
+    #[test]
+    fn test_policy() {
+        let from: Url = "https://example.com/a".parse().unwrap();
+        let same: Url = "https://example.com:443/b".parse().unwrap();
+        let other: Url = "https://www.example.com/a".parse().unwrap();
+
+        let follow: RedirectPolicy = "follow".parse().unwrap();
+        assert!(follow.allows(9, &from, &other));
+        assert!(!follow.allows(10, &from, &other));
+        assert!(!follow.with_max_hops(2).allows(2, &from, &same));
+
+        let same_host: RedirectPolicy = "same-host".parse().unwrap();
+        assert!(same_host.allows(0, &from, &same));
+        assert!(!same_host.allows(0, &from, &other));
+
+        assert!(!RedirectPolicy::None.allows(0, &from, &same));
+        assert!("sometimes".parse::<RedirectPolicy>().is_err());
+    }
+}
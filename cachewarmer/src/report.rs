@@ -0,0 +1,154 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Outcome of warming a single URL, as seen by a CI report.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestCase {
+    pub url: String,
+    pub time: Duration,
+    pub failures: Vec<String>,
+}
+
+impl TestCase {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn write_junit<W: Write>(mut out: W, cases: &[TestCase]) -> Result<(), Error> {
+    let failures = cases.iter().filter(|case| !case.passed()).count();
+    let time: Duration = cases.iter().map(|case| case.time).sum();
+
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuite name="cachewarmer" tests="{}" failures="{}" time="{:.3}">"#,
+        cases.len(),
+        failures,
+        time.as_secs_f64()
+    )?;
+
+    for case in cases {
+        let name = xml_escape(&case.url);
+        let time = case.time.as_secs_f64();
+        if case.passed() {
+            writeln!(
+                out,
+                r#"  <testcase classname="cachewarmer" name="{}" time="{:.3}"/>"#,
+                name, time
+            )?;
+            continue;
+        }
+
+        writeln!(
+            out,
+            r#"  <testcase classname="cachewarmer" name="{}" time="{:.3}">"#,
+            name, time
+        )?;
+        for failure in &case.failures {
+            let failure = xml_escape(failure);
+            writeln!(
+                out,
+                r#"    <failure message="{}">{}</failure>"#,
+                failure, failure
+            )?;
+        }
+        writeln!(out, "  </testcase>")?;
+    }
+
+    writeln!(out, "</testsuite>")
+}
+
+pub fn write_tap<W: Write>(mut out: W, cases: &[TestCase]) -> Result<(), Error> {
+    writeln!(out, "TAP version 13")?;
+    writeln!(out, "1..{}", cases.len())?;
+
+    for (i, case) in cases.iter().enumerate() {
+        if case.passed() {
+            writeln!(out, "ok {} - {}", i + 1, case.url)?;
+            continue;
+        }
+
+        writeln!(out, "not ok {} - {}", i + 1, case.url)?;
+        writeln!(out, "  ---")?;
+        writeln!(out, "  failures:")?;
+        for failure in &case.failures {
+            writeln!(out, "    - {:?}", failure)?;
+        }
+        writeln!(out, "  ...")?;
+    }
+
+    Ok(())
+}
+
+pub fn save_junit<P: AsRef<Path>>(path: P, cases: &[TestCase]) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_junit(&mut out, cases)?;
+    out.flush()
+}
+
+pub fn save_tap<P: AsRef<Path>>(path: P, cases: &[TestCase]) -> Result<(), Error> {
+    let mut out = BufWriter::new(File::create(path)?);
+    write_tap(&mut out, cases)?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::report::{TestCase, write_junit, write_tap};
+    use std::time::Duration;
+
+    fn cases() -> Vec<TestCase> {
+        vec![
+            TestCase {
+                url: "https://example.com/?a=1&b=2".to_string(),
+                time: Duration::from_millis(250),
+                failures: vec![],
+            },
+            TestCase {
+                url: "https://example.com/missing".to_string(),
+                time: Duration::from_millis(500),
+                failures: vec!["status 404, expected 200".to_string()],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_junit() {
+        let mut out = Vec::new();
+        write_junit(&mut out, &cases()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.contains(r#"tests="2" failures="1" time="0.750""#));
+        assert!(out.contains(r#"name="https://example.com/?a=1&amp;b=2" time="0.250"/>"#));
+        assert!(out.contains(r#"<failure message="status 404, expected 200">"#));
+    }
+
+    #[test]
+    fn test_tap() {
+        let mut out = Vec::new();
+        write_tap(&mut out, &cases()).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("TAP version 13\n1..2\nok 1 - https://example.com/?a=1&b=2\n"));
+        assert!(out.contains("not ok 2 - https://example.com/missing\n"));
+        assert!(out.contains("    - \"status 404, expected 200\"\n"));
+    }
+}
@@ -0,0 +1,212 @@
+use crate::adaptive::ConcurrencyReport;
+use crate::breaker::BreakerReport;
+use crate::cacheability::CacheabilityReport;
+use crate::checkpoint::Checkpoint;
+use crate::compare::RunResults;
+use crate::fetch::Outcome;
+use crate::fingerprint::Fingerprints;
+use crate::groups::GroupedStats;
+use crate::origin::OriginReport;
+use crate::report::TestCase;
+use crate::sink::ResultSink;
+use crate::stats::Stats;
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Results gathered over one warm run.
+pub struct Run {
+    pub checkpoint: Checkpoint,
+    pub cases: Vec<TestCase>,
+    pub by_proxy: BTreeMap<String, Stats>,
+    pub results: RunResults,
+    pub groups: GroupedStats,
+    pub cacheability: CacheabilityReport,
+    pub fingerprints: Fingerprints,
+    /// Responses compared with the origin's, if they were.
+    pub origin: OriginReport,
+    /// URLs left out as duplicates of others after normalization.
+    pub merged: usize,
+    /// Hosts whose circuit breaker opened.
+    pub breakers: BreakerReport,
+    /// How adaptive concurrency limits changed, if there were any.
+    pub concurrency: ConcurrencyReport,
+    // every successful response, where the checkpoint's totals only count
+    // the URLs it won't retry on resume
+    totals: Stats,
+    checkpoint_path: Option<PathBuf>,
+    last_checkpoint: Instant,
+    sinks: Vec<Box<dyn ResultSink>>,
+    // a sink failing mid-run shouldn't stop the warming, so its error is
+    // kept for `finish`
+    sink_error: Option<Error>,
+}
+
+impl Run {
+    pub fn new(checkpoint: Checkpoint, checkpoint_path: Option<PathBuf>) -> Self {
+        Run {
+            checkpoint,
+            cases: Vec::new(),
+            by_proxy: BTreeMap::new(),
+            results: RunResults::default(),
+            groups: GroupedStats::default(),
+            cacheability: CacheabilityReport::default(),
+            fingerprints: Fingerprints::default(),
+            origin: OriginReport::default(),
+            merged: 0,
+            breakers: BreakerReport::default(),
+            concurrency: ConcurrencyReport::default(),
+            totals: Stats::new(),
+            checkpoint_path,
+            last_checkpoint: Instant::now(),
+            sinks: Vec::new(),
+            sink_error: None,
+        }
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn ResultSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Take the sinks back, e.g. to pass them on to the next run.
+    pub fn take_sinks(&mut self) -> Vec<Box<dyn ResultSink>> {
+        std::mem::take(&mut self.sinks)
+    }
+
+    /// Stats of every successful request of this run, through every
+    /// proxy and redirect targets included, like [`Run::cases`].
+    pub fn totals(&self) -> &Stats {
+        &self.totals
+    }
+
+    pub fn failed(&self) -> usize {
+        self.cases.iter().filter(|case| !case.passed()).count()
+    }
+
+    pub fn record(&mut self, mut outcome: Outcome) {
+        let targets = std::mem::take(&mut outcome.targets);
+        self.record_one(outcome, true);
+        // redirect targets aren't in the URL list, so there's nothing to
+        // checkpoint for them
+        for target in targets {
+            self.record_one(target, false);
+        }
+    }
+
+    fn record_one(&mut self, outcome: Outcome, checkpoint: bool) {
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.record(&outcome) {
+                self.sink_error.get_or_insert(e);
+            }
+        }
+
+        let direct = outcome.routes.len() == 1 && outcome.routes[0].proxy == crate::proxy::DIRECT;
+        let mut url_stats = Stats::new();
+        let mut all_done = true;
+
+        for route in outcome.routes {
+            let name = if direct {
+                outcome.url.clone()
+            } else {
+                format!("{} via {}", outcome.url, route.proxy)
+            };
+
+            self.results.record(&outcome.url, &route.result);
+            self.groups.record(&outcome.url, &route.result);
+            self.cacheability.record(&outcome.url, &route.result);
+            self.fingerprints
+                .record(&outcome.url, &route.proxy, &route.result);
+            if let Some(origin) = &outcome.origin {
+                self.origin
+                    .record(&outcome.url, &route.proxy, &route.result, origin);
+            }
+            match route.result {
+                Ok(fetched) => {
+                    url_stats.aggregate(&fetched.stats);
+                    self.totals.aggregate(&fetched.stats);
+                    self.by_proxy
+                        .entry(route.proxy)
+                        .or_default()
+                        .aggregate(&fetched.stats);
+                    self.cases.push(TestCase {
+                        url: name,
+                        time: fetched.stats.elapsed_time,
+                        failures: fetched.failures,
+                    });
+                }
+                Err(e) => {
+                    all_done = false;
+                    self.cases.push(TestCase {
+                        url: name,
+                        time: Duration::ZERO,
+                        failures: vec![format!("request failed: {}", e)],
+                    });
+                }
+            }
+        }
+
+        // retry the url on resume unless it went through every proxy
+        if checkpoint && all_done {
+            self.checkpoint.record(outcome.index, &url_stats);
+        }
+    }
+
+    /// Count a line of the URL list that isn't a valid job as failed.
+    pub fn record_invalid(&mut self, error: &Error) {
+        eprintln!("skipping invalid line {}", error);
+        self.cases.push(TestCase {
+            url: "invalid line".to_string(),
+            time: Duration::ZERO,
+            failures: vec![error.to_string()],
+        });
+    }
+
+    /// Hand the final results to every sink.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        let mut sinks = self.take_sinks();
+        let mut result = match self.sink_error.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        };
+        for sink in &mut sinks {
+            let finished = sink.finish(self);
+            result = result.and(finished);
+        }
+        self.sinks = sinks;
+
+        result
+    }
+
+    pub fn save_checkpoint(&mut self) -> Result<(), Error> {
+        if let Some(path) = &self.checkpoint_path {
+            self.checkpoint.save(path)?;
+            self.last_checkpoint = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Save the checkpoint if it is time to. A failed save is only logged,
+    /// as a transient write error shouldn't stop the warming; the final
+    /// [`Run::save_checkpoint`] reports it if it persists.
+    pub fn save_checkpoint_if_due(&mut self) {
+        if self.last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL
+            && let Err(e) = self.save_checkpoint()
+        {
+            eprintln!("saving checkpoint failed: {}", e);
+            self.last_checkpoint = Instant::now();
+        }
+    }
+
+    pub fn print_partial(&self) {
+        let totals = self.totals();
+        println!(
+            "partial {:?} ({:.2} bytes/sec)",
+            totals,
+            totals.bytes_per_sec().unwrap_or_default()
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use signal_hook::consts::{SIGINT, SIGTERM, SIGUSR1};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long in-flight requests may keep running after a stop signal.
+pub const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How often the main loops look at the signal flags.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Flags raised by SIGINT/SIGTERM (stop) and SIGUSR1 (dump current totals).
+///
+/// A second SIGINT/SIGTERM while already stopping terminates the process
+/// right away, with the usual 128 + signal number exit code.
+#[derive(Clone, Debug)]
+pub struct Signals {
+    stop: Arc<AtomicBool>,
+    dump: Arc<AtomicBool>,
+}
+
+impl Signals {
+    pub fn install() -> Result<Self, std::io::Error> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let dump = Arc::new(AtomicBool::new(false));
+
+        for (signal, status) in [(SIGINT, 130), (SIGTERM, 143)] {
+            // the conditional shutdown must be registered first so that it
+            // only fires once `stop` has already been set by an earlier signal
+            signal_hook::flag::register_conditional_shutdown(signal, status, stop.clone())?;
+            signal_hook::flag::register(signal, stop.clone())?;
+        }
+        signal_hook::flag::register(SIGUSR1, dump.clone())?;
+
+        Ok(Signals { stop, dump })
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// Stop as if a signal had arrived, e.g. when a deadline passes.
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn take_dump_request(&self) -> bool {
+        self.dump.swap(false, Ordering::Relaxed)
+    }
+
+    pub async fn stopped(&self) {
+        while !self.stop_requested() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,506 @@
+use crate::fetch::{Outcome, RouteResult};
+use crate::proxy::DIRECT;
+use crate::redirect::Hop;
+use crate::run::Run;
+use crate::timing::Phases;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Error, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Receives the result of every URL as it completes and the aggregate
+/// once the run is over.
+///
+/// Any `FnMut(&Outcome)` closure is a sink that ignores the aggregate.
+pub trait ResultSink: Send {
+    fn record(&mut self, outcome: &Outcome) -> Result<(), Error>;
+
+    fn finish(&mut self, _run: &Run) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&Outcome) + Send> ResultSink for F {
+    fn record(&mut self, outcome: &Outcome) -> Result<(), Error> {
+        self(outcome);
+        Ok(())
+    }
+}
+
+fn route_name(outcome: &Outcome, route: &RouteResult) -> String {
+    if route.proxy == DIRECT {
+        outcome.url.clone()
+    } else {
+        format!("{} via {}", outcome.url, route.proxy)
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Human readable output: failures as they happen (and every URL when
+/// verbose) on stderr, totals on stdout.
+#[derive(Debug, Default)]
+pub struct ConsoleSink {
+    verbose: bool,
+}
+
+impl ConsoleSink {
+    pub fn new(verbose: bool) -> Self {
+        ConsoleSink { verbose }
+    }
+}
+
+impl ResultSink for ConsoleSink {
+    fn record(&mut self, outcome: &Outcome) -> Result<(), Error> {
+        for route in &outcome.routes {
+            let name = route_name(outcome, route);
+            match &route.result {
+                Ok(fetched) => {
+                    for failure in &fetched.failures {
+                        eprintln!("{} check failed: {}", name, failure);
+                    }
+                    if self.verbose {
+                        for hop in &fetched.redirects {
+                            eprintln!("{} redirect {}", name, hop);
+                        }
+                        eprintln!(
+                            "{} {} {:?} ({})",
+                            name, fetched.status, fetched.stats, fetched.phases
+                        );
+                    }
+                }
+                Err(e) => eprintln!("{} failed: {}", name, e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, run: &Run) -> Result<(), Error> {
+        if run.by_proxy.keys().any(|proxy| proxy != DIRECT) {
+            for (proxy, stats) in &run.by_proxy {
+                println!(
+                    "proxy {} {:?} ({:.2} bytes/sec)",
+                    proxy,
+                    stats,
+                    stats.bytes_per_sec().unwrap_or_default()
+                );
+            }
+        }
+
+        if !run.groups.is_empty() {
+            print!("{}", run.groups);
+        }
+
+        if !run.cacheability.is_empty() {
+            print!("{}", run.cacheability);
+        }
+
+        if !run.origin.is_empty() {
+            print!("{}", run.origin);
+        }
+
+        if !run.concurrency.is_empty() {
+            print!("{}", run.concurrency);
+        }
+
+        if !run.breakers.is_empty() {
+            print!("{}", run.breakers);
+        }
+
+        if run.merged > 0 {
+            println!("merged {} duplicate urls", run.merged);
+        }
+
+        let totals = run.totals();
+        if !totals.phases.is_empty() {
+            print!("{}", totals.phases);
+        }
+        println!(
+            "total {:?} ({:.2} bytes/sec)",
+            totals,
+            totals.bytes_per_sec().unwrap_or_default()
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonLine<'a> {
+    Result {
+        url: &'a str,
+        proxy: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        status: Option<u16>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        elapsed_ms: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        content_length: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sha256: Option<&'a str>,
+        #[serde(skip_serializing_if = "<[Hop]>::is_empty")]
+        redirects: &'a [Hop],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        phases: Option<PhaseLine>,
+        #[serde(skip_serializing_if = "<[String]>::is_empty")]
+        failures: &'a [String],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Total {
+        requests: usize,
+        failed: usize,
+        elapsed_ms: f64,
+        content_length: usize,
+    },
+}
+
+#[derive(Serialize)]
+struct PhaseLine {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dns_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    connect_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_ms: Option<f64>,
+    ttfb_ms: f64,
+    pooled: bool,
+}
+
+impl PhaseLine {
+    fn new(phases: &Phases) -> Self {
+        PhaseLine {
+            dns_ms: phases.dns.map(millis),
+            connect_ms: phases.connect.map(millis),
+            tls_ms: phases.tls.map(millis),
+            ttfb_ms: millis(phases.ttfb),
+            pooled: phases.pooled,
+        }
+    }
+}
+
+/// One JSON object per request, then one with the totals.
+pub struct JsonLinesSink<W> {
+    out: W,
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Send> JsonLinesSink<W> {
+    pub fn new(out: W) -> Self {
+        JsonLinesSink { out }
+    }
+
+    fn write_line(&mut self, line: &JsonLine) -> Result<(), Error> {
+        serde_json::to_writer(&mut self.out, line)?;
+        writeln!(self.out)
+    }
+}
+
+impl<W: Write + Send> ResultSink for JsonLinesSink<W> {
+    fn record(&mut self, outcome: &Outcome) -> Result<(), Error> {
+        for route in &outcome.routes {
+            let line = match &route.result {
+                Ok(fetched) => JsonLine::Result {
+                    url: &outcome.url,
+                    proxy: &route.proxy,
+                    status: Some(fetched.status.as_u16()),
+                    elapsed_ms: Some(millis(fetched.stats.elapsed_time)),
+                    content_length: Some(fetched.stats.content_length),
+                    sha256: Some(&fetched.sha256),
+                    redirects: &fetched.redirects,
+                    phases: Some(PhaseLine::new(&fetched.phases)),
+                    failures: &fetched.failures,
+                    error: None,
+                },
+                Err(e) => JsonLine::Result {
+                    url: &outcome.url,
+                    proxy: &route.proxy,
+                    status: None,
+                    elapsed_ms: None,
+                    content_length: None,
+                    sha256: None,
+                    redirects: &[],
+                    phases: None,
+                    failures: &[],
+                    error: Some(e.to_string()),
+                },
+            };
+            self.write_line(&line)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, run: &Run) -> Result<(), Error> {
+        let totals = run.totals();
+        self.write_line(&JsonLine::Total {
+            requests: run.cases.len(),
+            failed: run.failed(),
+            elapsed_ms: millis(totals.elapsed_time),
+            content_length: totals.content_length,
+        })?;
+        self.out.flush()
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// One row per request. Failed requests have empty status, timing and size.
+pub struct CsvSink<W> {
+    out: W,
+    header_written: bool,
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write + Send> CsvSink<W> {
+    pub fn new(out: W) -> Self {
+        CsvSink {
+            out,
+            header_written: false,
+        }
+    }
+}
+
+impl<W: Write + Send> ResultSink for CsvSink<W> {
+    fn record(&mut self, outcome: &Outcome) -> Result<(), Error> {
+        if !self.header_written {
+            writeln!(self.out, "url,proxy,status,elapsed_ms,content_length,error")?;
+            self.header_written = true;
+        }
+
+        for route in &outcome.routes {
+            let (status, elapsed_ms, content_length, error) = match &route.result {
+                Ok(fetched) => (
+                    fetched.status.as_u16().to_string(),
+                    format!("{:.3}", millis(fetched.stats.elapsed_time)),
+                    fetched.stats.content_length.to_string(),
+                    fetched.failures.join("; "),
+                ),
+                Err(e) => (String::new(), String::new(), String::new(), e.to_string()),
+            };
+            writeln!(
+                self.out,
+                "{},{},{},{},{},{}",
+                csv_field(&outcome.url),
+                csv_field(&route.proxy),
+                status,
+                elapsed_ms,
+                content_length,
+                csv_field(&error)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self, _run: &Run) -> Result<(), Error> {
+        self.out.flush()
+    }
+}
+
+fn label_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The totals in the Prometheus text format, for the node_exporter
+/// textfile collector. The file is replaced after every run.
+#[derive(Debug)]
+pub struct MetricsSink {
+    path: PathBuf,
+}
+
+impl MetricsSink {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        MetricsSink { path: path.into() }
+    }
+
+    fn render(run: &Run, now: SystemTime) -> String {
+        let totals = run.totals();
+        let timestamp = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut out = String::new();
+
+        for (name, help, value) in [
+            (
+                "cachewarmer_requests",
+                "Requests sent in the last run.",
+                run.cases.len().to_string(),
+            ),
+            (
+                "cachewarmer_failed",
+                "Requests that failed or did not pass their checks in the last run.",
+                run.failed().to_string(),
+            ),
+            (
+                "cachewarmer_request_seconds",
+                "Time spent in requests in the last run.",
+                totals.elapsed_time.as_secs_f64().to_string(),
+            ),
+            (
+                "cachewarmer_response_bytes",
+                "Response body bytes received in the last run.",
+                totals.content_length.to_string(),
+            ),
+            (
+                "cachewarmer_last_run_timestamp_seconds",
+                "When the last run finished.",
+                timestamp.as_secs().to_string(),
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} gauge", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        if run.by_proxy.keys().any(|proxy| proxy != DIRECT) {
+            let _ = writeln!(
+                out,
+                "# HELP cachewarmer_proxy_response_bytes Response body bytes received through each proxy in the last run."
+            );
+            let _ = writeln!(out, "# TYPE cachewarmer_proxy_response_bytes gauge");
+            for (proxy, stats) in &run.by_proxy {
+                let _ = writeln!(
+                    out,
+                    "cachewarmer_proxy_response_bytes{{proxy=\"{}\"}} {}",
+                    label_escape(proxy),
+                    stats.content_length
+                );
+            }
+        }
+
+        out
+    }
+}
+
+impl ResultSink for MetricsSink {
+    fn record(&mut self, _outcome: &Outcome) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn finish(&mut self, run: &Run) -> Result<(), Error> {
+        // the collector may read the file at any moment, so never let it
+        // see a half written one
+        let mut tmp_path = self.path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        std::fs::write(&tmp_path, Self::render(run, SystemTime::now()))?;
+        std::fs::rename(&tmp_path, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::checkpoint::Checkpoint;
+    use crate::fetch::{Fetched, Outcome, RouteResult};
+    use crate::run::Run;
+    use crate::sink::{CsvSink, JsonLinesSink, MetricsSink, ResultSink};
+    use crate::stats::Stats;
+    use crate::timing::Phases;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn outcomes() -> Vec<Outcome> {
+        vec![
+            Outcome {
+                index: 0,
+                url: "http://localhost/a,b".to_string(),
+                routes: vec![RouteResult {
+                    proxy: "direct".to_string(),
+                    result: Ok(Fetched {
+                        stats: Stats {
+                            elapsed_time: Duration::from_millis(20),
+                            content_length: 100,
+                            ..Stats::new()
+                        },
+                        url: "http://localhost/a,b".to_string(),
+                        phases: Phases {
+                            dns: Some(Duration::from_millis(1)),
+                            connect: Some(Duration::from_millis(2)),
+                            tls: None,
+                            ttfb: Duration::from_millis(15),
+                            pooled: false,
+                        },
+                        sha256: "2c26b46b".to_string(),
+                        failures: vec!["status 404, expected 200".to_string()],
+                        ..Fetched::with_status(404)
+                    }),
+                }],
+                targets: Vec::new(),
+                origin: None,
+            },
+            Outcome {
+                index: 1,
+                url: "http://localhost/c".to_string(),
+                routes: vec![RouteResult {
+                    proxy: "direct".to_string(),
+                    result: Err("connection refused".into()),
+                }],
+                targets: Vec::new(),
+                origin: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_sinks() {
+        let mut jsonl = JsonLinesSink::new(Vec::new());
+        let mut csv = CsvSink::new(Vec::new());
+        let mut run = Run::new(Checkpoint::new(2), None);
+        for outcome in outcomes() {
+            jsonl.record(&outcome).unwrap();
+            csv.record(&outcome).unwrap();
+            run.record(outcome);
+        }
+        jsonl.finish(&run).unwrap();
+        csv.finish(&run).unwrap();
+
+        assert_eq!(
+            String::from_utf8(jsonl.out).unwrap(),
+            concat!(
+                r#"{"type":"result","url":"http://localhost/a,b","proxy":"direct","status":404,"elapsed_ms":20.0,"content_length":100,"sha256":"2c26b46b","phases":{"dns_ms":1.0,"connect_ms":2.0,"ttfb_ms":15.0,"pooled":false},"failures":["status 404, expected 200"]}"#,
+                "\n",
+                r#"{"type":"result","url":"http://localhost/c","proxy":"direct","error":"connection refused"}"#,
+                "\n",
+                r#"{"type":"total","requests":2,"failed":2,"elapsed_ms":20.0,"content_length":100}"#,
+                "\n",
+            )
+        );
+
+        assert_eq!(
+            String::from_utf8(csv.out).unwrap(),
+            concat!(
+                "url,proxy,status,elapsed_ms,content_length,error\n",
+                "\"http://localhost/a,b\",direct,404,20.000,100,\"status 404, expected 200\"\n",
+                "http://localhost/c,direct,,,,connection refused\n",
+            )
+        );
+
+        let metrics = MetricsSink::render(&run, UNIX_EPOCH + Duration::from_secs(1000));
+        assert!(metrics.contains("\ncachewarmer_failed 2\n"));
+        assert!(metrics.contains("\ncachewarmer_response_bytes 100\n"));
+        assert!(metrics.contains("\ncachewarmer_last_run_timestamp_seconds 1000\n"));
+    }
+}
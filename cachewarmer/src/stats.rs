@@ -0,0 +1,39 @@
+use crate::timing::PhaseStats;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub elapsed_time: Duration,
+    pub content_length: usize,
+    /// Connection phases of every request; not saved.
+    #[serde(skip)]
+    pub phases: PhaseStats,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Stats {
+            elapsed_time: Duration::default(),
+            content_length: 0,
+            phases: PhaseStats::default(),
+        }
+    }
+
+    pub fn aggregate(&mut self, other: &Stats) {
+        self.elapsed_time += other.elapsed_time;
+        self.content_length += other.content_length;
+        self.phases.aggregate(&other.phases);
+    }
+
+    pub fn bytes_per_sec(&self) -> Option<f64> {
+        let elapsed_sec = self.elapsed_time.as_secs_f64();
+        if elapsed_sec < 0.001 {
+            return None;
+        }
+
+        let bytes = self.content_length as f64;
+
+        Some(bytes / elapsed_sec)
+    }
+}
@@ -0,0 +1,482 @@
+use crate::fetch::{Fetcher, Job, Outcome};
+use crate::run::Run;
+use crate::signals::{GRACE_PERIOD, POLL_INTERVAL, Signals};
+use futures::stream::{FuturesUnordered, StreamExt};
+use std::fmt;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Instant;
+use tokio::runtime::Handle;
+
+/// How requests are scheduled. These mirror the scheduling of the lab
+/// levels, not their request code: every strategy sends requests through
+/// the same async [`Fetcher`], so that comparing them compares only the
+/// scheduling. The `block-on-*` strategies run on plain OS threads, each
+/// driving the fetcher with `block_on` where the levels use
+/// `reqwest::blocking`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Strategy {
+    /// One request at a time on the calling thread (level7).
+    BlockOnSequential,
+    /// A thread per URL, aggregating under a shared mutex (level8).
+    BlockOnThreadPerUrl,
+    /// A thread per URL, sending results back over a channel (level9).
+    BlockOnThreadChannel,
+    /// One request at a time, awaited on the async runtime (level10).
+    AsyncSequential,
+    /// Many requests at once as a `FuturesUnordered` stream (level11), up
+    /// to the executor's concurrency limit.
+    FuturesUnordered,
+    /// A fixed number of threads taking URLs off a shared queue.
+    BlockOnWorkerPool,
+}
+
+impl Strategy {
+    pub const ALL: [Strategy; 6] = [
+        Strategy::BlockOnSequential,
+        Strategy::BlockOnThreadPerUrl,
+        Strategy::BlockOnThreadChannel,
+        Strategy::AsyncSequential,
+        Strategy::FuturesUnordered,
+        Strategy::BlockOnWorkerPool,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::BlockOnSequential => "block-on-sequential",
+            Strategy::BlockOnThreadPerUrl => "block-on-thread-per-url",
+            Strategy::BlockOnThreadChannel => "block-on-thread-channel",
+            Strategy::AsyncSequential => "async-sequential",
+            Strategy::FuturesUnordered => "futures-unordered",
+            Strategy::BlockOnWorkerPool => "block-on-worker-pool",
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        Strategy::ALL
+            .into_iter()
+            .find(|strategy| strategy.name() == name)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Unknown strategy {:?}", name),
+                )
+            })
+    }
+}
+
+/// Periodic housekeeping while requests are in flight: SIGUSR1 dumps,
+/// checkpoints and the grace period after a stop signal.
+struct Control<'a> {
+    signals: &'a Signals,
+    deadline: Option<Instant>,
+}
+
+impl<'a> Control<'a> {
+    fn new(signals: &'a Signals) -> Self {
+        Control {
+            signals,
+            deadline: None,
+        }
+    }
+
+    /// Returns false once in-flight requests should be abandoned.
+    fn tick(&mut self, run: &Mutex<Run>) -> bool {
+        let mut run = run.lock().unwrap();
+        if self.signals.take_dump_request() {
+            run.print_partial();
+        }
+
+        run.save_checkpoint_if_due();
+
+        if self.signals.stop_requested() {
+            let deadline = *self
+                .deadline
+                .get_or_insert_with(|| Instant::now() + GRACE_PERIOD);
+            if Instant::now() >= deadline {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+async fn with_grace<F: Future>(signals: &Signals, fut: F) -> Option<F::Output> {
+    tokio::select! {
+        output = fut => Some(output),
+        _ = async {
+            signals.stopped().await;
+            tokio::time::sleep(GRACE_PERIOD).await;
+        } => None,
+    }
+}
+
+/// Requests in flight at once with [`Strategy::FuturesUnordered`] unless
+/// set otherwise.
+pub const DEFAULT_CONCURRENCY: usize = 64;
+
+/// Runs jobs with a given strategy. The `block-on-*` strategies drive the
+/// async fetcher from plain OS threads through the runtime handle, so every
+/// strategy shares the same request code.
+pub struct Executor {
+    runtime: Handle,
+    signals: Signals,
+    workers: usize,
+    concurrency: usize,
+}
+
+impl Executor {
+    pub fn new(runtime: Handle, signals: Signals, workers: usize) -> Self {
+        Executor {
+            runtime,
+            signals,
+            workers: workers.max(1),
+            concurrency: DEFAULT_CONCURRENCY,
+        }
+    }
+
+    /// How many requests [`Strategy::FuturesUnordered`] and
+    /// [`follow`](Executor::follow) keep in flight; jobs are taken from
+    /// the list only as earlier ones finish.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    /// Run `jobs` in the order given, taking each only when it is about to
+    /// start. Put the most important first, e.g. with [`Job::sort_by_priority`]
+    /// or a [`crate::input::JobList`], so that they are done even if the
+    /// run is cut short.
+    pub fn execute<I>(
+        &self,
+        strategy: Strategy,
+        fetcher: Arc<Fetcher>,
+        jobs: I,
+        run: Arc<Mutex<Run>>,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Job>,
+        I::IntoIter: Send + 'static,
+    {
+        let jobs = jobs.into_iter();
+        match strategy {
+            Strategy::BlockOnSequential => self.sequential(fetcher, jobs, &run),
+            Strategy::BlockOnThreadPerUrl => self.thread_per_url(fetcher, jobs, run),
+            Strategy::BlockOnThreadChannel => self.thread_channel(fetcher, jobs, &run),
+            Strategy::AsyncSequential => self.async_sequential(fetcher, jobs, &run),
+            Strategy::FuturesUnordered => self.futures_unordered(fetcher, jobs, &run),
+            Strategy::BlockOnWorkerPool => self.worker_pool(fetcher, jobs, &run),
+        }
+    }
+
+    fn sequential(
+        &self,
+        fetcher: Arc<Fetcher>,
+        jobs: impl Iterator<Item = Job>,
+        run: &Mutex<Run>,
+    ) -> Result<(), Error> {
+        let mut control = Control::new(&self.signals);
+        for job in jobs {
+            if self.signals.stop_requested() || !control.tick(run) {
+                break;
+            }
+
+            let outcome = self
+                .runtime
+                .block_on(with_grace(&self.signals, fetcher.fetch(job)));
+            match outcome {
+                Some(outcome) => run.lock().unwrap().record(outcome),
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn spawn_per_job<F>(&self, jobs: impl Iterator<Item = Job>, body: F) -> Vec<JoinHandle<()>>
+    where
+        F: Fn(Job) + Clone + Send + 'static,
+    {
+        let mut threads = Vec::new();
+        for job in jobs {
+            if self.signals.stop_requested() {
+                break;
+            }
+
+            let body = body.clone();
+            threads.push(std::thread::spawn(move || body(job)));
+        }
+
+        threads
+    }
+
+    fn join_finished(threads: Vec<JoinHandle<()>>) {
+        for thread in threads.into_iter().filter(|thread| thread.is_finished()) {
+            thread.join().unwrap();
+        }
+    }
+
+    fn thread_per_url(
+        &self,
+        fetcher: Arc<Fetcher>,
+        jobs: impl Iterator<Item = Job>,
+        run: Arc<Mutex<Run>>,
+    ) -> Result<(), Error> {
+        let runtime = self.runtime.clone();
+        let totals = run.clone();
+        let threads = self.spawn_per_job(jobs, move |job| {
+            let outcome = runtime.block_on(fetcher.fetch(job));
+            totals.lock().unwrap().record(outcome);
+        });
+
+        let mut control = Control::new(&self.signals);
+        while !threads.iter().all(|thread| thread.is_finished()) {
+            if !control.tick(&run) {
+                break;
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        Self::join_finished(threads);
+        Ok(())
+    }
+
+    fn thread_channel(
+        &self,
+        fetcher: Arc<Fetcher>,
+        jobs: impl Iterator<Item = Job>,
+        run: &Mutex<Run>,
+    ) -> Result<(), Error> {
+        let runtime = self.runtime.clone();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let threads = self.spawn_per_job(jobs, move |job| {
+            let outcome = runtime.block_on(fetcher.fetch(job));
+            // the receiver is gone if we gave up waiting
+            let _ = sender.send(outcome);
+        });
+
+        self.drain(receiver, run)?;
+        Self::join_finished(threads);
+        Ok(())
+    }
+
+    fn worker_pool(
+        &self,
+        fetcher: Arc<Fetcher>,
+        jobs: impl Iterator<Item = Job> + Send + 'static,
+        run: &Mutex<Run>,
+    ) -> Result<(), Error> {
+        let jobs = Arc::new(Mutex::new(jobs.into_iter()));
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let threads: Vec<_> = (0..self.workers)
+            .map(|_| {
+                let runtime = self.runtime.clone();
+                let signals = self.signals.clone();
+                let fetcher = fetcher.clone();
+                let jobs = jobs.clone();
+                let sender = sender.clone();
+                std::thread::spawn(move || {
+                    while !signals.stop_requested() {
+                        let Some(job) = jobs.lock().unwrap().next() else {
+                            break;
+                        };
+
+                        let outcome = runtime.block_on(fetcher.fetch(job));
+                        if sender.send(outcome).is_err() {
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        drop(sender);
+
+        self.drain(receiver, run)?;
+        Self::join_finished(threads);
+        Ok(())
+    }
+
+    fn drain(&self, receiver: Receiver<Outcome>, run: &Mutex<Run>) -> Result<(), Error> {
+        let mut control = Control::new(&self.signals);
+        loop {
+            match receiver.recv_timeout(POLL_INTERVAL) {
+                Ok(outcome) => run.lock().unwrap().record(outcome),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            if !control.tick(run) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn async_sequential(
+        &self,
+        fetcher: Arc<Fetcher>,
+        jobs: impl Iterator<Item = Job>,
+        run: &Mutex<Run>,
+    ) -> Result<(), Error> {
+        self.runtime.block_on(async {
+            let mut control = Control::new(&self.signals);
+            for job in jobs {
+                if self.signals.stop_requested() || !control.tick(run) {
+                    break;
+                }
+
+                match with_grace(&self.signals, fetcher.fetch(job)).await {
+                    Some(outcome) => run.lock().unwrap().record(outcome),
+                    None => break,
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    fn futures_unordered(
+        &self,
+        fetcher: Arc<Fetcher>,
+        jobs: impl Iterator<Item = Job>,
+        run: &Mutex<Run>,
+    ) -> Result<(), Error> {
+        self.runtime.block_on(async {
+            let mut jobs = jobs.into_iter();
+            let mut requests = FuturesUnordered::new();
+            let mut poll = tokio::time::interval(POLL_INTERVAL);
+            let mut control = Control::new(&self.signals);
+
+            loop {
+                // top up the requests in flight, but start none once stopping
+                while requests.len() < self.concurrency && !self.signals.stop_requested() {
+                    let Some(job) = jobs.next() else {
+                        break;
+                    };
+                    requests.push(fetcher.fetch(job));
+                }
+                if requests.is_empty() {
+                    break;
+                }
+
+                tokio::select! {
+                    Some(outcome) = requests.next() => {
+                        run.lock().unwrap().record(outcome);
+                    }
+                    _ = poll.tick() => {
+                        if !control.tick(run) {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Warm jobs as they come in, e.g. from a list that is still being
+    /// written, starting each as soon as it arrives and fewer than the
+    /// concurrency limit are in flight; jobs are not reordered, so
+    /// priorities make no difference here.
+    /// Invalid lines count as failed and reading goes on; a read error stops
+    /// it. Returns once `jobs` runs out and every request finished.
+    pub fn follow<I>(&self, fetcher: Arc<Fetcher>, jobs: I, run: &Mutex<Run>) -> Result<(), Error>
+    where
+        I: Iterator<Item = Result<Job, Error>> + Send + 'static,
+    {
+        // reading may block, so it gets a thread of its own
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(1024);
+        let reader = std::thread::spawn(move || {
+            for job in jobs {
+                if sender.blocking_send(job).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let result = self.runtime.block_on(async {
+            let mut requests = FuturesUnordered::new();
+            let mut reading = true;
+            let mut read_error = None;
+            let mut poll = tokio::time::interval(POLL_INTERVAL);
+            let mut control = Control::new(&self.signals);
+
+            loop {
+                reading &= !self.signals.stop_requested();
+                if !reading && requests.is_empty() {
+                    break;
+                }
+
+                tokio::select! {
+                    job = receiver.recv(), if reading && requests.len() < self.concurrency => match job {
+                        Some(Ok(job)) => requests.push(fetcher.fetch(job)),
+                        Some(Err(e)) if e.kind() == ErrorKind::InvalidInput => {
+                            fetcher.progress().failed();
+                            run.lock().unwrap().record_invalid(&e);
+                        }
+                        Some(Err(e)) => {
+                            read_error = Some(e);
+                            reading = false;
+                        }
+                        None => reading = false,
+                    },
+                    Some(outcome) = requests.next(), if !requests.is_empty() => {
+                        run.lock().unwrap().record(outcome);
+                    }
+                    _ = poll.tick() => {
+                        if !control.tick(run) {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            read_error.map_or(Ok(()), Err)
+        });
+
+        // a followed file ends within a poll interval of a stop; stdin and
+        // URLs can only end when they are closed, so the reader is left to
+        // the end of the process if it is still blocked by then
+        drop(receiver);
+        let deadline = Instant::now() + 2 * POLL_INTERVAL;
+        while !reader.is_finished() && Instant::now() < deadline {
+            std::thread::sleep(POLL_INTERVAL / 10);
+        }
+        if reader.is_finished() {
+            reader.join().unwrap();
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::strategy::Strategy;
+
+    #[test]
+    fn test_strategy_names() {
+        for strategy in Strategy::ALL {
+            assert_eq!(strategy.name().parse::<Strategy>().unwrap(), strategy);
+        }
+
+        assert!("threads".parse::<Strategy>().is_err());
+        assert!("sequential".parse::<Strategy>().is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::Arc;
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidInput, msg)
+}
+
+/// Rows of a CSV file with a header, for `{column}` placeholders.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Csv {
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
+
+impl Csv {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Quoted fields may contain commas and doubled quotes, not newlines.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = split_csv_line(lines.next().unwrap_or_default());
+        let mut rows = Vec::new();
+        for line in lines {
+            let row = split_csv_line(line);
+            if row.len() != header.len() {
+                return Err(invalid(format!(
+                    "CSV row has {} fields, the header {}: {}",
+                    row.len(),
+                    header.len(),
+                    line
+                )));
+            }
+            rows.push(row);
+        }
+
+        Ok(Csv { header, rows })
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        self.header.iter().position(|column| column.trim() == name)
+    }
+}
+
+/// One set of values a template iterates over.
+#[derive(Clone, Debug, PartialEq)]
+enum Dimension {
+    Range {
+        start: i64,
+        step: i64,
+        count: u64,
+        // zero padded to this many digits, as in {001..100}
+        width: usize,
+    },
+    Alternatives(Vec<String>),
+    // every CSV column of a template comes from the same row
+    Rows(Arc<Csv>),
+}
+
+impl Dimension {
+    fn count(&self) -> u64 {
+        match self {
+            Dimension::Range { count, .. } => *count,
+            Dimension::Alternatives(values) => values.len() as u64,
+            Dimension::Rows(csv) => csv.rows.len() as u64,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Value(usize),
+    Column(usize, usize),
+}
+
+/// A URL with placeholders that expands into many:
+///
+/// * `{1..5000}` numbers (`{001..100}` zero padded, `{0..100..10}` with a step),
+/// * `{webp,avif}` each of the alternatives,
+/// * `{id}` the values of the `id` column of a CSV file.
+///
+/// Every combination is produced, the last placeholder changing fastest;
+/// several columns of the CSV file change together, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+    dimensions: Vec<Dimension>,
+}
+
+// none if `spec` isn't a range at all
+fn parse_range(spec: &str) -> Result<Option<Dimension>, Error> {
+    let parts: Vec<_> = spec.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, "1"),
+        [start, end, step] => (start, end, step),
+        _ => return Ok(None),
+    };
+    let is_number = |part: &str| {
+        let digits = part.strip_prefix('-').unwrap_or(part);
+        !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+    };
+    if ![start, end, step].into_iter().all(is_number) {
+        return Ok(None);
+    }
+
+    let out_of_range = || invalid(format!("Range {{{}}} is out of range", spec));
+    let number = |part: &str| part.parse::<i64>().map_err(|_| out_of_range());
+    let (first, last) = (number(start)?, number(end)?);
+    let step = number(step)?.checked_abs().ok_or_else(out_of_range)?;
+    if step == 0 {
+        return Ok(None);
+    }
+    let step = if last < first { -step } else { step };
+    let width = if start.len() > 1 && start.starts_with('0') {
+        start.len()
+    } else {
+        0
+    };
+    let span = last.checked_sub(first).ok_or_else(out_of_range)?;
+    let count = (span.unsigned_abs() / step.unsigned_abs())
+        .checked_add(1)
+        .ok_or_else(out_of_range)?;
+
+    Ok(Some(Dimension::Range {
+        start: first,
+        step,
+        count,
+        width,
+    }))
+}
+
+impl Template {
+    /// A URL taken as it is, braces and all.
+    pub fn literal(url: &str) -> Self {
+        Template {
+            segments: vec![Segment::Literal(url.to_string())],
+            dimensions: Vec::new(),
+        }
+    }
+
+    pub fn parse(template: &str, csv: Option<&Arc<Csv>>) -> Result<Self, Error> {
+        let mut segments = Vec::new();
+        let mut dimensions = Vec::new();
+        let mut rows = None;
+        let mut rest = template;
+
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| invalid(format!("Unclosed {{ in {:?}", template)))?
+                + open;
+            let spec = &rest[open + 1..close];
+            rest = &rest[close + 1..];
+
+            if let Some(range) = parse_range(spec)? {
+                segments.push(Segment::Value(dimensions.len()));
+                dimensions.push(range);
+            } else if spec.contains(',') {
+                let values = spec.split(',').map(str::to_string).collect();
+                segments.push(Segment::Value(dimensions.len()));
+                dimensions.push(Dimension::Alternatives(values));
+            } else {
+                let csv = csv.ok_or_else(|| {
+                    invalid(format!("{{{}}} in {:?} needs a CSV file", spec, template))
+                })?;
+                let column = csv.column(spec).ok_or_else(|| {
+                    invalid(format!(
+                        "No column {:?} in the CSV file for {:?}",
+                        spec, template
+                    ))
+                })?;
+                let rows = *rows.get_or_insert_with(|| {
+                    dimensions.push(Dimension::Rows(csv.clone()));
+                    dimensions.len() - 1
+                });
+                segments.push(Segment::Column(rows, column));
+            }
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(Template {
+            segments,
+            dimensions,
+        })
+    }
+
+    /// How many URLs the template expands to, without expanding it.
+    pub fn count(&self) -> u64 {
+        self.dimensions
+            .iter()
+            .map(Dimension::count)
+            .fold(1, u64::saturating_mul)
+    }
+
+    /// The URLs, one at a time.
+    pub fn expand(self) -> Expansion {
+        let done = self
+            .dimensions
+            .iter()
+            .any(|dimension| dimension.count() == 0);
+        Expansion {
+            positions: vec![0; self.dimensions.len()],
+            template: self,
+            done,
+        }
+    }
+}
+
+/// The URLs of a [`Template`], made on demand.
+#[derive(Clone, Debug)]
+pub struct Expansion {
+    template: Template,
+    positions: Vec<u64>,
+    done: bool,
+}
+
+impl Iterator for Expansion {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        if self.done {
+            return None;
+        }
+
+        let dimensions = &self.template.dimensions;
+        let mut url = String::new();
+        for segment in &self.template.segments {
+            match *segment {
+                Segment::Literal(ref literal) => url.push_str(literal),
+                Segment::Value(index) => match &dimensions[index] {
+                    Dimension::Range {
+                        start, step, width, ..
+                    } => {
+                        // parse_range made sure the whole range fits
+                        let value = i64::try_from(self.positions[index])
+                            .ok()
+                            .and_then(|position| step.checked_mul(position))
+                            .and_then(|offset| start.checked_add(offset))
+                            .expect("range value out of range");
+                        url.push_str(&format!("{:0width$}", value, width = *width));
+                    }
+                    Dimension::Alternatives(values) => {
+                        url.push_str(&values[self.positions[index] as usize]);
+                    }
+                    Dimension::Rows(_) => unreachable!(),
+                },
+                Segment::Column(index, column) => {
+                    let Dimension::Rows(csv) = &dimensions[index] else {
+                        unreachable!()
+                    };
+                    url.push_str(&csv.rows[self.positions[index] as usize][column]);
+                }
+            }
+        }
+
+        // advance like an odometer, the last dimension fastest
+        self.done = true;
+        for (position, dimension) in self.positions.iter_mut().zip(dimensions).rev() {
+            *position += 1;
+            if *position < dimension.count() {
+                self.done = false;
+                break;
+            }
+            *position = 0;
+        }
+
+        Some(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::{Csv, Template};
+    use std::sync::Arc;
+
+    fn expand(template: &str, csv: Option<&Arc<Csv>>) -> Vec<String> {
+        let template = Template::parse(template, csv).unwrap();
+        let count = template.count();
+        let urls: Vec<_> = template.expand().collect();
+        assert_eq!(urls.len() as u64, count);
+        urls
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand("/p/{1..3}", None), ["/p/1", "/p/2", "/p/3"]);
+        assert_eq!(expand("/p/{3..1}", None), ["/p/3", "/p/2", "/p/1"]);
+        assert_eq!(expand("/p/{08..10}", None), ["/p/08", "/p/09", "/p/10"]);
+        assert_eq!(expand("/p/{0..25..10}", None), ["/p/0", "/p/10", "/p/20"]);
+        assert_eq!(
+            expand("/i?w={320,640}&fmt={webp,avif}", None),
+            [
+                "/i?w=320&fmt=webp",
+                "/i?w=320&fmt=avif",
+                "/i?w=640&fmt=webp",
+                "/i?w=640&fmt=avif"
+            ]
+        );
+
+        let csv = Arc::new(Csv::parse("id,slug\n7,\"a, b\"\n9,c\n").unwrap());
+        assert_eq!(
+            expand("/img/{id}/{slug}?w={1..2}", Some(&csv)),
+            [
+                "/img/7/a, b?w=1",
+                "/img/7/a, b?w=2",
+                "/img/9/c?w=1",
+                "/img/9/c?w=2"
+            ]
+        );
+
+        assert_eq!(
+            Template::parse("/p/{1..100000}/{1..100000}", None)
+                .unwrap()
+                .count(),
+            10_000_000_000
+        );
+        assert!(Template::parse("/img/{id}", None).is_err());
+        assert!(Template::parse("/img/{name}", Some(&csv)).is_err());
+        assert!(Template::parse("/p/{1..3", None).is_err());
+        assert!(Template::parse("/p/{-9223372036854775808..9223372036854775807}", None).is_err());
+        assert!(Template::parse("/p/{0..99999999999999999999}", None).is_err());
+        assert_eq!(
+            expand("/p/{9223372036854775806..9223372036854775807}", None),
+            ["/p/9223372036854775806", "/p/9223372036854775807"]
+        );
+        let literal: Vec<_> = Template::literal("/p/{1..3}").expand().collect();
+        assert_eq!(literal, ["/p/{1..3}"]);
+        assert!(Csv::parse("id,slug\n7\n").is_err());
+    }
+}
@@ -0,0 +1,374 @@
+use crate::histogram::Histogram;
+use reqwest::ClientBuilder;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use rustls::crypto::{CryptoProvider, GetRandomFailed, SecureRandom};
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower_layer::Layer;
+use tower_service::Service;
+
+tokio::task_local! {
+    // the request being sent, for the connector to report back to
+    static REQUEST: Arc<RequestTiming>;
+    // the connection being set up, for the resolver and TLS to report back to
+    static CONNECTION: Arc<ConnectionTiming>;
+}
+
+/// How long each phase of a single request took.
+///
+/// The setup phases are only known for requests that opened a connection
+/// of their own; `pooled` ones reused an idle one and went straight to
+/// sending.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Phases {
+    /// Name lookup; none for IP addresses.
+    pub dns: Option<Duration>,
+    /// TCP connect, after the lookup, including any proxy handshake.
+    pub connect: Option<Duration>,
+    /// TLS handshake, after the connect.
+    pub tls: Option<Duration>,
+    /// From sending the request until the response headers arrived,
+    /// including any connection setup.
+    pub ttfb: Duration,
+    pub pooled: bool,
+}
+
+impl Phases {
+    pub fn get(&self, phase: Phase) -> Option<Duration> {
+        match phase {
+            Phase::Dns => self.dns,
+            Phase::Connect => self.connect,
+            Phase::Tls => self.tls,
+            Phase::Ttfb => Some(self.ttfb),
+        }
+    }
+}
+
+impl fmt::Display for Phases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if self.pooled {
+            parts.push("pooled".to_string());
+        }
+        for phase in Phase::ALL {
+            if let Some(duration) = self.get(phase) {
+                parts.push(format!("{} {:?}", phase, duration));
+            }
+        }
+
+        f.write_str(&parts.join(" "))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Phase {
+    Dns,
+    Connect,
+    Tls,
+    Ttfb,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [Phase::Dns, Phase::Connect, Phase::Tls, Phase::Ttfb];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Dns => "dns",
+            Phase::Connect => "connect",
+            Phase::Tls => "tls",
+            Phase::Ttfb => "ttfb",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The phases of many requests, for percentiles.
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct PhaseStats {
+    pub requests: usize,
+    /// Requests that reused a pooled connection.
+    pub pooled: usize,
+    // one per phase, in Phase::ALL order
+    samples: [Histogram; 4],
+}
+
+impl PhaseStats {
+    pub fn record(&mut self, phases: &Phases) {
+        self.requests += 1;
+        self.pooled += phases.pooled as usize;
+        for (phase, samples) in Phase::ALL.into_iter().zip(&mut self.samples) {
+            if let Some(duration) = phases.get(phase) {
+                samples.record(duration);
+            }
+        }
+    }
+
+    pub fn aggregate(&mut self, other: &PhaseStats) {
+        self.requests += other.requests;
+        self.pooled += other.pooled;
+        for (samples, other) in self.samples.iter_mut().zip(&other.samples) {
+            samples.aggregate(other);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0
+    }
+
+    pub fn percentile(&self, phase: Phase, pct: f64) -> Option<Duration> {
+        self.samples[phase as usize].percentile(pct)
+    }
+}
+
+// the buckets would swamp the output
+impl fmt::Debug for PhaseStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PhaseStats")
+            .field("requests", &self.requests)
+            .field("pooled", &self.pooled)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for PhaseStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "phases of {} requests ({} on pooled connections):",
+            self.requests, self.pooled
+        )?;
+        for phase in Phase::ALL {
+            let latency = |pct| match self.percentile(phase, pct) {
+                Some(latency) => format!("{:?}", latency),
+                None => "-".to_string(),
+            };
+            writeln!(
+                f,
+                "  {:<8} {:>6} samples  p50 {:<12} p90 {:<12} p99 {:<12}",
+                phase,
+                self.samples[phase as usize].len(),
+                latency(50.0),
+                latency(90.0),
+                latency(99.0)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct RequestTiming {
+    // the connection opened for the request, if any
+    connection: Mutex<Option<Arc<ConnectionTiming>>>,
+}
+
+struct ConnectionTiming {
+    start: Instant,
+    dns: Mutex<Option<Duration>>,
+    tls_start: OnceLock<Instant>,
+    end: OnceLock<Instant>,
+}
+
+impl ConnectionTiming {
+    fn new() -> Self {
+        ConnectionTiming {
+            start: Instant::now(),
+            dns: Mutex::new(None),
+            tls_start: OnceLock::new(),
+            end: OnceLock::new(),
+        }
+    }
+}
+
+/// Time connection setup of every client built from `builder`. Clients
+/// not built this way report every request as pooled.
+///
+/// Telling the TLS handshake apart from the TCP connect also needs the
+/// client to do its handshakes with [`crypto_provider`]; otherwise the
+/// handshake counts as part of the connect.
+pub fn timed(builder: ClientBuilder) -> ClientBuilder {
+    builder
+        .dns_resolver(TimedResolver)
+        .connector_layer(TimedConnectLayer)
+}
+
+/// The rustls provider for clients built with [`timed`], for
+/// [`crate::tls::TlsConfig::client_config`].
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    static RANDOM: LazyLock<TimedRandom> =
+        LazyLock::new(|| TimedRandom(rustls::crypto::aws_lc_rs::default_provider().secure_random));
+
+    Arc::new(CryptoProvider {
+        secure_random: &*RANDOM,
+        ..rustls::crypto::aws_lc_rs::default_provider()
+    })
+}
+
+/// Send a request, returning its response and how long each phase took.
+pub async fn measure<F: Future>(send: F) -> (F::Output, Phases) {
+    let request = Arc::new(RequestTiming::default());
+    let start = Instant::now();
+    let output = REQUEST.scope(request.clone(), send).await;
+    let ttfb = start.elapsed();
+
+    let connection = request.connection.lock().unwrap().take();
+    // a connection that isn't done yet lost the race against one that
+    // became idle in the meantime
+    let phases = match connection {
+        Some(connection) if connection.end.get().is_some() => {
+            let end = *connection.end.get().unwrap();
+            let dns = *connection.dns.lock().unwrap();
+            let connected = connection.tls_start.get().copied().unwrap_or(end);
+            Phases {
+                dns,
+                connect: Some(
+                    (connected - connection.start).saturating_sub(dns.unwrap_or_default()),
+                ),
+                tls: connection.tls_start.get().map(|tls_start| end - *tls_start),
+                ttfb,
+                pooled: false,
+            }
+        }
+        _ => Phases {
+            ttfb,
+            pooled: true,
+            ..Phases::default()
+        },
+    };
+
+    (output, phases)
+}
+
+struct TimedResolver;
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let connection = CONNECTION.try_with(Arc::clone).ok();
+        let host = format!("{}:0", name.as_str());
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs = tokio::net::lookup_host(host).await?;
+            if let Some(connection) = connection {
+                *connection.dns.lock().unwrap() = Some(start.elapsed());
+            }
+            Ok(Box::new(addrs) as Addrs)
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TimedConnectLayer;
+
+impl<S> Layer<S> for TimedConnectLayer {
+    type Service = TimedConnect<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TimedConnect { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct TimedConnect<S> {
+    inner: S,
+}
+
+impl<S, R> Service<R> for TimedConnect<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+    S::Response: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<S::Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let connection = Arc::new(ConnectionTiming::new());
+        let _ = REQUEST.try_with(|request| {
+            *request.connection.lock().unwrap() = Some(connection.clone());
+        });
+
+        let connect = CONNECTION.sync_scope(connection.clone(), || self.inner.call(req));
+        Box::pin(CONNECTION.scope(connection.clone(), async move {
+            let result = connect.await;
+            let _ = connection.end.set(Instant::now());
+            result
+        }))
+    }
+}
+
+// rustls needs randomness for the ClientHello before anything else, so
+// the first call marks the start of the handshake
+#[derive(Debug)]
+struct TimedRandom(&'static dyn SecureRandom);
+
+impl SecureRandom for TimedRandom {
+    fn fill(&self, buf: &mut [u8]) -> Result<(), GetRandomFailed> {
+        let _ = CONNECTION.try_with(|connection| {
+            connection.tls_start.get_or_init(Instant::now);
+        });
+        self.0.fill(buf)
+    }
+
+    fn fips(&self) -> bool {
+        self.0.fips()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timing::{Phase, PhaseStats, Phases};
+    use std::time::Duration;
+
+    #[test]
+    fn test_phase_stats() {
+        let fresh = Phases {
+            dns: Some(Duration::from_millis(2)),
+            connect: Some(Duration::from_millis(10)),
+            tls: None,
+            ttfb: Duration::from_millis(30),
+            pooled: false,
+        };
+        let pooled = Phases {
+            ttfb: Duration::from_millis(20),
+            pooled: true,
+            ..Phases::default()
+        };
+        assert_eq!(fresh.to_string(), "dns 2ms connect 10ms ttfb 30ms");
+        assert_eq!(pooled.to_string(), "pooled ttfb 20ms");
+
+        let mut stats = PhaseStats::default();
+        stats.record(&fresh);
+        let mut other = PhaseStats::default();
+        other.record(&pooled);
+        other.record(&pooled);
+        stats.aggregate(&other);
+
+        assert_eq!((stats.requests, stats.pooled), (3, 2));
+        assert_eq!(
+            stats.percentile(Phase::Connect, 99.0),
+            Some(Duration::from_millis(10))
+        );
+        assert_eq!(stats.percentile(Phase::Tls, 50.0), None);
+        assert_eq!(
+            stats.percentile(Phase::Ttfb, 50.0),
+            Some(Duration::from_millis(20))
+        );
+    }
+}
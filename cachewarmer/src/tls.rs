@@ -0,0 +1,286 @@
+use reqwest::tls::TlsInfo;
+use reqwest::{ClientBuilder, Response};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::sign::{CertifiedKey, SingleCertAndKey};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use rustls_platform_verifier::Verifier;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_parser::prelude::{FromDer, X509Certificate};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// TLS settings shared by every client of a run.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    ca_certs: Vec<CertificateDer<'static>>,
+    identity: Option<CertifiedKey>,
+    insecure: bool,
+}
+
+impl TlsConfig {
+    /// Trust the certificates in a PEM bundle on top of the built-in roots.
+    pub fn add_ca_bundle<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        let pem = std::fs::read(path)?;
+        for cert in CertificateDer::pem_slice_iter(&pem) {
+            self.ca_certs.push(cert.map_err(Error::other)?);
+        }
+        Ok(())
+    }
+
+    /// Present a client certificate (mTLS). The key may also be part of the
+    /// certificate file, in which case `key_path` can be omitted.
+    pub fn set_client_cert<P: AsRef<Path>>(
+        &mut self,
+        cert_path: P,
+        key_path: Option<P>,
+    ) -> Result<(), Error> {
+        let mut pem = std::fs::read(cert_path)?;
+        if let Some(key_path) = key_path {
+            pem.push(b'\n');
+            pem.extend(std::fs::read(key_path)?);
+        }
+
+        let certs = CertificateDer::pem_slice_iter(&pem)
+            .collect::<Result<_, _>>()
+            .map_err(Error::other)?;
+        let key = PrivateKeyDer::from_pem_slice(&pem).map_err(Error::other)?;
+        let provider = rustls::crypto::aws_lc_rs::default_provider();
+        self.identity = Some(CertifiedKey::from_der(certs, key, &provider).map_err(Error::other)?);
+        Ok(())
+    }
+
+    /// DANGEROUS: accept any server certificate, including expired,
+    /// self-signed and mismatched ones. Only meant for staging origins.
+    pub fn set_insecure(&mut self, insecure: bool) {
+        self.insecure = insecure;
+    }
+
+    pub fn is_insecure(&self) -> bool {
+        self.insecure
+    }
+
+    /// The rustls config for these settings, doing the handshakes with
+    /// `provider`; to be handed to clients with [`configure`].
+    pub fn client_config(&self, provider: Arc<CryptoProvider>) -> Result<ClientConfig, Error> {
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(Error::other)?
+            .dangerous();
+        let builder = if self.insecure {
+            builder.with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+        } else {
+            let verifier = Verifier::new_with_extra_roots(self.ca_certs.iter().cloned(), provider)
+                .map_err(Error::other)?;
+            builder.with_custom_certificate_verifier(Arc::new(verifier))
+        };
+
+        let mut config = match &self.identity {
+            Some(identity) => builder
+                .with_client_cert_resolver(Arc::new(SingleCertAndKey::from(identity.clone()))),
+            None => builder.with_no_client_auth(),
+        };
+        // what reqwest offers when it sets up TLS itself
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+/// Set up `builder` to use `config`, made by [`TlsConfig::client_config`].
+pub fn configure(builder: ClientBuilder, config: &ClientConfig) -> ClientBuilder {
+    builder
+        .tls_backend_preconfigured(config.clone())
+        // keep the peer certificate around for the expiry report
+        .tls_info(true)
+}
+
+// any certificate goes, but the handshake itself must still add up
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// The interesting bits of a server's leaf certificate.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CertInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: SystemTime,
+}
+
+impl CertInfo {
+    pub fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let not_after = cert.validity().not_after.timestamp();
+
+        Some(CertInfo {
+            subject: cert.subject().to_string(),
+            issuer: cert.issuer().to_string(),
+            not_after: UNIX_EPOCH + Duration::from_secs(not_after.max(0) as u64),
+        })
+    }
+
+    /// Whole days left until expiry, negative once expired.
+    pub fn days_left(&self, now: SystemTime) -> i64 {
+        match self.not_after.duration_since(now) {
+            Ok(left) => (left.as_secs() / DAY.as_secs()) as i64,
+            // started days count, so a day and a second ago is -2
+            Err(e) => -(e.duration().as_secs().div_ceil(DAY.as_secs()) as i64),
+        }
+    }
+}
+
+/// Leaf certificates seen while warming, one per `host:port`.
+#[derive(Clone, Debug, Default)]
+pub struct CertReport {
+    certs: BTreeMap<String, CertInfo>,
+}
+
+impl CertReport {
+    pub fn record(&mut self, resp: &Response) {
+        let url = resp.url();
+        let host = format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port_or_known_default().unwrap_or_default()
+        );
+
+        if self.certs.contains_key(&host) {
+            return;
+        }
+
+        let cert = resp
+            .extensions()
+            .get::<TlsInfo>()
+            .and_then(TlsInfo::peer_certificate)
+            .and_then(CertInfo::from_der);
+
+        if let Some(cert) = cert {
+            self.certs.insert(host, cert);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.certs.is_empty()
+    }
+
+    /// Hosts whose certificate expires within `days` days, soonest first.
+    pub fn expiring_within(&self, days: u64, now: SystemTime) -> Vec<(&str, &CertInfo)> {
+        // a limit past what SystemTime can hold takes in every certificate
+        let limit = now.checked_add(Duration::from_secs(days.saturating_mul(DAY.as_secs())));
+        let mut expiring: Vec<_> = self
+            .certs
+            .iter()
+            .filter(|(_, cert)| limit.is_none_or(|limit| cert.not_after <= limit))
+            .map(|(host, cert)| (host.as_str(), cert))
+            .collect();
+
+        expiring.sort_by_key(|(_, cert)| cert.not_after);
+        expiring
+    }
+}
+
+impl fmt::Display for CertInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let days_left = self.days_left(SystemTime::now());
+        if days_left < 0 {
+            write!(f, "EXPIRED {} days ago", -days_left)?;
+        } else {
+            write!(f, "expires in {} days", days_left)?;
+        }
+
+        write!(f, " (subject {}, issuer {})", self.subject, self.issuer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tls::{CertInfo, CertReport, DAY};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn cert(expires_in_days: u32) -> CertInfo {
+        CertInfo {
+            subject: "CN=example.com".to_string(),
+            issuer: "CN=Internal CA".to_string(),
+            not_after: UNIX_EPOCH + DAY * (1000 + expires_in_days),
+        }
+    }
+
+    #[test]
+    fn test_expiring_within() {
+        let now = UNIX_EPOCH + DAY * 1000;
+        let mut report = CertReport::default();
+        report.certs.insert("a:443".to_string(), cert(90));
+        report.certs.insert("b:443".to_string(), cert(20));
+        report.certs.insert("c:443".to_string(), cert(5));
+
+        let hosts: Vec<_> = report
+            .expiring_within(30, now)
+            .into_iter()
+            .map(|(host, _)| host)
+            .collect();
+        assert_eq!(hosts, ["c:443", "b:443"]);
+        assert_eq!(report.expiring_within(u64::MAX, now).len(), 3);
+    }
+
+    #[test]
+    fn test_days_left() {
+        let now = UNIX_EPOCH + DAY * 1000;
+        assert_eq!(cert(20).days_left(now), 20);
+        assert_eq!(cert(0).days_left(now + DAY / 2), -1);
+        assert_eq!(cert(0).days_left(now + DAY), -1);
+        assert_eq!(cert(0).days_left(now + DAY + DAY / 2), -2);
+        assert_eq!(cert(0).days_left(now), 0);
+        assert!(cert(0).days_left(SystemTime::now()) < 0);
+    }
+}
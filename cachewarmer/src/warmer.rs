@@ -0,0 +1,376 @@
+use crate::adaptive::AdaptiveConfig;
+use crate::auth::{Auth, Credentials};
+use crate::breaker::BreakerConfig;
+use crate::checkpoint::Checkpoint;
+use crate::fetch::{Fetcher, Job, Outcome};
+use crate::groups::{GroupKey, GroupedStats};
+use crate::origin::{self, Origin, OriginReport};
+use crate::progress::Progress;
+use crate::proxy::{PoolMode, ProxyConfig, ProxyPool};
+use crate::redirect::RedirectPolicy;
+use crate::run::Run;
+use crate::sink::ResultSink;
+use crate::timing;
+use crate::tls::{self, CertReport, TlsConfig};
+use futures::stream::{Stream, StreamExt};
+use reqwest::cookie::Jar;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::io::Error;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+/// Settings for a [`Warmer`].
+pub struct WarmerBuilder {
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    headers: HeaderMap,
+    concurrency: usize,
+    retries: usize,
+    retry_backoff: Duration,
+    proxies: Vec<ProxyConfig>,
+    proxy_mode: PoolMode,
+    tls: TlsConfig,
+    credentials: Option<(Credentials, Vec<String>)>,
+    cookie_jar: Option<Arc<Jar>>,
+    sinks: Vec<Box<dyn ResultSink>>,
+    group_by: Vec<GroupKey>,
+    redirects: RedirectPolicy,
+    warm_redirect_targets: bool,
+    breaker: Option<BreakerConfig>,
+    adaptive: Option<AdaptiveConfig>,
+    origin: Option<Origin>,
+    compare_headers: Vec<HeaderName>,
+}
+
+impl Default for WarmerBuilder {
+    fn default() -> Self {
+        WarmerBuilder {
+            timeout: None,
+            connect_timeout: None,
+            user_agent: None,
+            headers: HeaderMap::new(),
+            concurrency: 16,
+            retries: 0,
+            retry_backoff: Duration::from_millis(100),
+            proxies: Vec::new(),
+            proxy_mode: PoolMode::Every,
+            tls: TlsConfig::default(),
+            credentials: None,
+            cookie_jar: None,
+            sinks: Vec::new(),
+            group_by: Vec::new(),
+            redirects: RedirectPolicy::default(),
+            warm_redirect_targets: false,
+            breaker: None,
+            adaptive: None,
+            origin: None,
+            compare_headers: Vec::new(),
+        }
+    }
+}
+
+impl WarmerBuilder {
+    /// Total time allowed for each request, including the body.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// A header sent with every request.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Maximum number of URLs in flight at once.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Retry failed requests up to `retries` times, waiting `backoff` before
+    /// the first retry and twice as long before each next one.
+    pub fn retries(mut self, retries: usize, backoff: Duration) -> Self {
+        self.retries = retries;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    pub fn proxy_mode(mut self, mode: PoolMode) -> Self {
+        self.proxy_mode = mode;
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Send `credentials` with the requests to `hosts` (host names, or
+    /// `host:port`), and no others.
+    pub fn credentials<I>(mut self, credentials: Credentials, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.credentials = Some((credentials, hosts.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    pub fn cookie_jar(mut self, jar: Arc<Jar>) -> Self {
+        self.cookie_jar = Some(jar);
+        self
+    }
+
+    /// Feed the results of [`Warmer::run`] to `sink`; may be repeated.
+    pub fn sink<S: ResultSink + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    pub fn redirects(mut self, policy: RedirectPolicy) -> Self {
+        self.redirects = policy;
+        self
+    }
+
+    /// Also warm every redirect target, once, as an entry of its own.
+    pub fn warm_redirect_targets(mut self, warm: bool) -> Self {
+        self.warm_redirect_targets = warm;
+        self
+    }
+
+    /// Break the totals of [`Warmer::run`] down by `key`; may be repeated.
+    pub fn group_by(mut self, key: GroupKey) -> Self {
+        self.group_by.push(key);
+        self
+    }
+
+    /// Stop sending requests to hosts that fail too often, for a while.
+    pub fn circuit_breaker(mut self, config: BreakerConfig) -> Self {
+        self.breaker = Some(config);
+        self
+    }
+
+    /// Adapt the requests in flight to each host to how it copes, within
+    /// the overall [`concurrency`](WarmerBuilder::concurrency).
+    pub fn adaptive(mut self, config: AdaptiveConfig) -> Self {
+        self.adaptive = Some(config);
+        self
+    }
+
+    /// Also fetch every URL from `origin`, and report responses that
+    /// differ from the origin's in [`Run::origin`](crate::run::Run::origin).
+    pub fn origin(mut self, origin: Origin) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Compare this header with the origin's; may be repeated. Without it,
+    /// [`origin::DEFAULT_HEADERS`] are compared.
+    pub fn compare_header(mut self, name: HeaderName) -> Self {
+        self.compare_headers.push(name);
+        self
+    }
+
+    pub fn build(self) -> Result<Warmer, Error> {
+        let tls = self.tls.client_config(timing::crypto_provider())?;
+        let client_builder = || {
+            let mut builder = tls::configure(timing::timed(reqwest::Client::builder()), &tls)
+                .default_headers(self.headers.clone())
+                .redirect(reqwest::redirect::Policy::none());
+            if let Some(timeout) = self.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(timeout) = self.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+            if let Some(user_agent) = &self.user_agent {
+                builder = builder.user_agent(user_agent);
+            }
+            if let Some(jar) = &self.cookie_jar {
+                builder = builder.cookie_provider(jar.clone());
+            }
+            builder
+        };
+        let pool =
+            ProxyPool::new(&self.proxies, self.proxy_mode, client_builder).map_err(Error::other)?;
+
+        let certs = Arc::new(Mutex::new(CertReport::default()));
+        let auth = self
+            .credentials
+            .map(|(credentials, hosts)| Arc::new(Auth::new(credentials, hosts)));
+        let mut fetcher = Fetcher::new(pool, auth, certs.clone(), Progress::new());
+        fetcher.set_retries(self.retries, self.retry_backoff);
+        fetcher.set_redirects(self.redirects, self.warm_redirect_targets);
+        if let Some(config) = self.adaptive {
+            fetcher.set_adaptive(config);
+        }
+        if let Some(config) = self.breaker {
+            fetcher.set_breaker(config);
+        }
+        if let Some(origin) = self.origin {
+            let client = origin
+                .client(client_builder())
+                .build()
+                .map_err(Error::other)?;
+            fetcher.set_origin(client, origin);
+        }
+        let compare_headers = match self.compare_headers.is_empty() {
+            true => origin::DEFAULT_HEADERS
+                .map(HeaderName::from_static)
+                .to_vec(),
+            false => self.compare_headers,
+        };
+
+        Ok(Warmer {
+            fetcher,
+            certs,
+            concurrency: self.concurrency,
+            sinks: tokio::sync::Mutex::new(self.sinks),
+            group_by: self.group_by,
+            compare_headers,
+            runtime: OnceLock::new(),
+        })
+    }
+}
+
+/// Warms a list of URLs with a fixed set of clients.
+///
+/// ```no_run
+/// # async fn warm() {
+/// use cachewarmer::Warmer;
+/// use std::time::Duration;
+///
+/// let warmer = Warmer::builder()
+///     .concurrency(32)
+///     .retries(2, Duration::from_millis(200))
+///     .build()
+///     .unwrap();
+///
+/// let run = warmer.run(["https://example.com/"]).await.unwrap();
+/// println!("{:?}", run.totals());
+/// # }
+/// ```
+pub struct Warmer {
+    fetcher: Fetcher,
+    certs: Arc<Mutex<CertReport>>,
+    concurrency: usize,
+    // held for a whole run, so that sinks see one run at a time
+    sinks: tokio::sync::Mutex<Vec<Box<dyn ResultSink>>>,
+    group_by: Vec<GroupKey>,
+    compare_headers: Vec<HeaderName>,
+    // only created for the blocking entry point
+    runtime: OnceLock<Runtime>,
+}
+
+impl Warmer {
+    pub fn builder() -> WarmerBuilder {
+        WarmerBuilder::default()
+    }
+
+    /// Fetch `jobs`, yielding the result of each as soon as it finishes.
+    ///
+    /// Jobs are started in the order given, whatever their priority. Plain
+    /// URLs are jobs too, and the index of each is its place in `jobs`.
+    pub fn stream<I>(&self, jobs: I) -> impl Stream<Item = Outcome> + Send + '_
+    where
+        I: IntoIterator,
+        I::Item: Into<Job>,
+        I::IntoIter: Send + 'static,
+    {
+        let jobs = jobs.into_iter().enumerate().map(|(index, job)| Job {
+            index,
+            ..job.into()
+        });
+
+        self.fetch_all(jobs)
+    }
+
+    fn fetch_all<I>(&self, jobs: I) -> impl Stream<Item = Outcome> + Send + '_
+    where
+        I: Iterator<Item = Job> + Send + 'static,
+    {
+        futures::stream::iter(jobs)
+            .map(|job| self.fetcher.fetch(job))
+            .buffer_unordered(self.concurrency)
+    }
+
+    /// Fetch `jobs`, starting those with the highest priority first, and
+    /// collect the results, passing them on to the sinks.
+    ///
+    /// Sink errors don't interrupt the run, they are returned at the end.
+    pub async fn run<I>(&self, jobs: I) -> Result<Run, Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<Job>,
+    {
+        let mut jobs: Vec<Job> = jobs
+            .into_iter()
+            .enumerate()
+            .map(|(index, job)| Job {
+                index,
+                ..job.into()
+            })
+            .collect();
+        Job::sort_by_priority(&mut jobs);
+        let mut run = Run::new(Checkpoint::new(jobs.len()), None);
+        run.groups = GroupedStats::new(&self.group_by);
+        run.origin = OriginReport::new(&self.compare_headers);
+        let mut sinks = self.sinks.lock().await;
+        for sink in sinks.drain(..) {
+            run.add_sink(sink);
+        }
+
+        let mut outcomes = std::pin::pin!(self.fetch_all(jobs.into_iter()));
+        while let Some(outcome) = outcomes.next().await {
+            run.record(outcome);
+        }
+
+        run.breakers = self.fetcher.breaker_report();
+        run.concurrency = self.fetcher.concurrency_report();
+        let finished = run.finish();
+        *sinks = run.take_sinks();
+        finished.map(|_| run)
+    }
+
+    /// Like [`Warmer::run`], for callers without an async runtime.
+    ///
+    /// Panics when called from within an async runtime.
+    pub fn run_blocking<I>(&self, jobs: I) -> Result<Run, Error>
+    where
+        I: IntoIterator,
+        I::Item: Into<Job>,
+    {
+        let runtime = match self.runtime.get() {
+            Some(runtime) => runtime,
+            None => {
+                let runtime = Runtime::new()?;
+                self.runtime.get_or_init(|| runtime)
+            }
+        };
+
+        runtime.block_on(self.run(jobs))
+    }
+
+    /// Certificates seen so far on TLS connections.
+    pub fn certs(&self) -> CertReport {
+        self.certs.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,99 @@
+mod mock;
+
+use cachewarmer::Warmer;
+use cachewarmer::auth::{Auth, Credentials};
+use mock::{MockServer, Route};
+use std::time::Instant;
+
+// stand-in for an OAuth2 token endpoint, numbering the tokens it hands out;
+// an `expires_in` within the refresh margin makes every token stale on arrival
+fn token_route(expires_in: u64) -> Route {
+    let body = format!(
+        r#"{{"access_token":"token-{{n}}","token_type":"Bearer","expires_in":{}}}"#,
+        expires_in
+    );
+    Route::ok()
+        .header("Content-Type", "application/json")
+        .body(&body)
+}
+
+fn oauth2(token_url: String) -> Credentials {
+    Credentials::OAuth2 {
+        token_url,
+        client_id: "warmer".to_string(),
+        client_secret: "secret".to_string(),
+        scope: None,
+    }
+}
+
+async fn authorization(auth: &Auth, client: &reqwest::Client) -> String {
+    let request = auth
+        .apply(client, client.get("http://localhost/"))
+        .await
+        .unwrap()
+        .build()
+        .unwrap();
+
+    request.headers()["authorization"]
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_oauth2_token_shared() {
+    let server = MockServer::start([("/token", token_route(3600))]).await;
+    let auth = Auth::new(oauth2(server.url("/token")), vec!["localhost".to_string()]);
+    let client = reqwest::Client::new();
+
+    let headers = futures::future::join_all((0..10).map(|_| authorization(&auth, &client))).await;
+
+    assert!(headers.iter().all(|header| header == "Bearer token-1"));
+    assert_eq!(server.hits("/token"), 1);
+
+    // requests rejected before a refresh share it
+    let rejected_at = Instant::now();
+    auth.invalidate(rejected_at).await;
+    assert_eq!(authorization(&auth, &client).await, "Bearer token-2");
+    auth.invalidate(rejected_at).await;
+    assert_eq!(authorization(&auth, &client).await, "Bearer token-2");
+    assert_eq!(server.hits("/token"), 2);
+}
+
+#[tokio::test]
+async fn test_oauth2_token_refresh() {
+    let server = MockServer::start([("/token", token_route(10))]).await;
+    let auth = Auth::new(oauth2(server.url("/token")), vec!["localhost".to_string()]);
+    let client = reqwest::Client::new();
+
+    assert_eq!(authorization(&auth, &client).await, "Bearer token-1");
+    assert_eq!(authorization(&auth, &client).await, "Bearer token-2");
+
+    auth.invalidate(Instant::now()).await;
+    assert_eq!(authorization(&auth, &client).await, "Bearer token-3");
+    assert_eq!(server.hits("/token"), 3);
+}
+
+#[tokio::test]
+async fn test_warmer_refreshes_rejected_token() {
+    // a site that only accepts the second token, as if the first had been
+    // revoked
+    let server = MockServer::start([
+        ("/token", token_route(3600)),
+        (
+            "/",
+            Route::ok()
+                .body("hello")
+                .require_header("authorization", "Bearer token-2"),
+        ),
+    ])
+    .await;
+    let warmer = Warmer::builder()
+        .credentials(oauth2(server.url("/token")), ["127.0.0.1"])
+        .build()
+        .unwrap();
+
+    let run = warmer.run([server.url("/")]).await.unwrap();
+    assert_eq!(run.totals().content_length, 5);
+    assert_eq!(server.hits("/token"), 2);
+}
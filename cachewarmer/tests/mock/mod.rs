@@ -0,0 +1,316 @@
+// not every test uses every knob
+#![allow(dead_code)]
+
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const CHUNK_SIZE: usize = 1024;
+
+/// Ways for a route to misbehave.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// Close the connection without responding.
+    Reset,
+    /// Promise the full body, send half of it and close the connection.
+    Truncate,
+}
+
+/// How the mock origin answers requests for one path.
+#[derive(Clone, Debug)]
+pub struct Route {
+    status: u16,
+    body_size: usize,
+    latency: Duration,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+    chunked: bool,
+    fault: Option<Fault>,
+    failures: usize,
+    required: Option<(String, String)>,
+}
+
+impl Route {
+    /// A 200 response with an empty body.
+    pub fn ok() -> Self {
+        Route {
+            status: 200,
+            body_size: 0,
+            latency: Duration::ZERO,
+            headers: Vec::new(),
+            body: None,
+            chunked: false,
+            fault: None,
+            failures: 0,
+            required: None,
+        }
+    }
+
+    pub fn redirect(status: u16, location: &str) -> Self {
+        Route::ok().status(status).header("Location", location)
+    }
+
+    pub fn fault(fault: Fault) -> Self {
+        Route {
+            fault: Some(fault),
+            ..Route::ok()
+        }
+    }
+
+    pub fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn body_size(mut self, body_size: usize) -> Self {
+        self.body_size = body_size;
+        self
+    }
+
+    /// Wait this long before sending the response.
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn cache_control(self, value: &str) -> Self {
+        self.header("Cache-Control", value)
+    }
+
+    /// Send this body instead of `body_size` bytes; `{n}` in it becomes the
+    /// number of the request to the path, counting from 1.
+    pub fn body(mut self, body: &str) -> Self {
+        self.body = Some(body.to_string());
+        self
+    }
+
+    /// Close the connection without responding to the first `failures`
+    /// requests, then answer as usual.
+    pub fn fail_first(mut self, failures: usize) -> Self {
+        self.failures = failures;
+        self
+    }
+
+    /// Answer requests without this header (value compared ignoring
+    /// case) with an empty 401.
+    pub fn require_header(mut self, name: &str, value: &str) -> Self {
+        self.required = Some((name.to_ascii_lowercase(), value.to_ascii_lowercase()));
+        self
+    }
+
+    /// Send the body with `Transfer-Encoding: chunked`.
+    pub fn chunked(mut self) -> Self {
+        self.chunked = true;
+        self
+    }
+
+    fn head(&self, content_length: Option<usize>) -> String {
+        let status = StatusCode::from_u16(self.status).unwrap();
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or("Unknown")
+        );
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        match content_length {
+            Some(length) => head.push_str(&format!("Content-Length: {}\r\n", length)),
+            None => head.push_str("Transfer-Encoding: chunked\r\n"),
+        }
+        head.push_str("\r\n");
+        head
+    }
+
+    /// Answer the `n`th request to the path, with the (lowercase) headers
+    /// it came with. Returns false if the connection must not be reused.
+    async fn respond(
+        &self,
+        conn: &mut TcpStream,
+        n: usize,
+        headers: &[(String, String)],
+    ) -> std::io::Result<bool> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        if n <= self.failures {
+            return Ok(false);
+        }
+        if let Some(required) = &self.required
+            && !headers.contains(required)
+        {
+            let unauthorized = Route::ok().status(401).head(Some(0));
+            conn.write_all(unauthorized.as_bytes()).await?;
+            return Ok(true);
+        }
+
+        let body = match &self.body {
+            Some(body) => body.replace("{n}", &n.to_string()).into_bytes(),
+            None => vec![b'x'; self.body_size],
+        };
+        match self.fault {
+            Some(Fault::Reset) => return Ok(false),
+            Some(Fault::Truncate) => {
+                conn.write_all(self.head(Some(body.len())).as_bytes())
+                    .await?;
+                conn.write_all(&body[..body.len() / 2]).await?;
+                return Ok(false);
+            }
+            None => {}
+        }
+
+        if self.chunked {
+            conn.write_all(self.head(None).as_bytes()).await?;
+            for chunk in body.chunks(CHUNK_SIZE) {
+                conn.write_all(format!("{:x}\r\n", chunk.len()).as_bytes())
+                    .await?;
+                conn.write_all(chunk).await?;
+                conn.write_all(b"\r\n").await?;
+            }
+            conn.write_all(b"0\r\n\r\n").await?;
+        } else {
+            conn.write_all(self.head(Some(body.len())).as_bytes())
+                .await?;
+            conn.write_all(&body).await?;
+        }
+
+        Ok(true)
+    }
+}
+
+/// A local HTTP/1.1 origin serving canned routes and counting requests.
+///
+/// Unknown paths get an empty 404. Connections are kept alive, as with
+/// a real origin.
+pub struct MockServer {
+    addr: SocketAddr,
+    hits: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+impl MockServer {
+    /// Start serving on a random port. Must be called within a tokio
+    /// runtime, which then keeps the server running.
+    pub async fn start<'a, I: IntoIterator<Item = (&'a str, Route)>>(routes: I) -> Self {
+        let routes: HashMap<String, Route> = routes
+            .into_iter()
+            .map(|(path, route)| (path.to_string(), route))
+            .collect();
+        let routes = Arc::new(routes);
+        let hits = Arc::new(Mutex::new(HashMap::new()));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (conn, _) = listener.accept().await.unwrap();
+                tokio::spawn(serve(conn, routes.clone(), counter.clone()));
+            }
+        });
+
+        MockServer { addr, hits }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Requests received for `path` (including any query string).
+    pub fn hits(&self, path: &str) -> usize {
+        self.hits.lock().unwrap().get(path).copied().unwrap_or(0)
+    }
+
+    pub fn total_hits(&self) -> usize {
+        self.hits.lock().unwrap().values().sum()
+    }
+}
+
+struct Request {
+    path: String,
+    // lowercase names and values
+    headers: Vec<(String, String)>,
+}
+
+// returns None once the client closes the connection; bodies are skipped
+async fn read_request(conn: &mut TcpStream, buf: &mut Vec<u8>) -> Option<Request> {
+    loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&buf[..end]).into_owned();
+            buf.drain(..end + 4);
+
+            let mut lines = head.lines();
+            let path = lines.next()?.split(' ').nth(1).unwrap_or("/").to_string();
+            let headers: Vec<_> = lines
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| {
+                    let lower = |text: &str| text.trim().to_ascii_lowercase();
+                    (lower(name), lower(value))
+                })
+                .collect();
+            let length = headers
+                .iter()
+                .find(|(name, _)| name == "content-length")
+                .and_then(|(_, value)| value.parse().ok())
+                .unwrap_or(0);
+            while buf.len() < length {
+                let mut chunk = [0; 4096];
+                match conn.read(&mut chunk).await {
+                    Ok(0) | Err(_) => return None,
+                    Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                }
+            }
+            buf.drain(..length);
+
+            return Some(Request { path, headers });
+        }
+
+        let mut chunk = [0; 4096];
+        match conn.read(&mut chunk).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+async fn serve(
+    mut conn: TcpStream,
+    routes: Arc<HashMap<String, Route>>,
+    hits: Arc<Mutex<HashMap<String, usize>>>,
+) {
+    let not_found = Route::ok().status(404);
+    let mut buf = Vec::new();
+
+    while let Some(request) = read_request(&mut conn, &mut buf).await {
+        let path = request.path;
+        let n = {
+            let mut hits = hits.lock().unwrap();
+            let count = hits.entry(path.clone()).or_default();
+            *count += 1;
+            *count
+        };
+
+        let route = routes
+            .get(&path)
+            .or_else(|| routes.get(path.split('?').next().unwrap()))
+            .unwrap_or(&not_found);
+
+        match route.respond(&mut conn, n, &request.headers).await {
+            Ok(true) => {}
+            _ => return,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+mod mock;
+
+use cachewarmer::Warmer;
+use cachewarmer::fetch::Outcome;
+use cachewarmer::redirect::RedirectPolicy;
+use cachewarmer::run::Run;
+use mock::{MockServer, Route};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+// (url, statuses of the hops followed, final status) of every entry
+type Seen = Arc<Mutex<Vec<(String, Vec<u16>, u16)>>>;
+
+fn start() -> (Runtime, MockServer) {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(async {
+        let server = MockServer::start([]).await;
+        // a second server for the routes that need the first one's address
+        let addr = server.addr();
+        MockServer::start([
+            ("/a", Route::redirect(301, "/b")),
+            ("/b", Route::redirect(302, "/c")),
+            ("/c", Route::ok().body_size(100)),
+            (
+                "/elsewhere",
+                Route::redirect(301, &format!("http://localhost:{}/c", addr.port())),
+            ),
+        ])
+        .await
+    });
+
+    (runtime, server)
+}
+
+fn warm(server: &MockServer, policy: RedirectPolicy, targets: bool, path: &str) -> (Seen, Run) {
+    let seen: Seen = Arc::default();
+    let sink = seen.clone();
+    let warmer = Warmer::builder()
+        .redirects(policy)
+        .warm_redirect_targets(targets)
+        .sink(move |outcome: &Outcome| {
+            let fetched = outcome.routes[0].result.as_ref().unwrap();
+            let hops = fetched.redirects.iter().map(|hop| hop.status).collect();
+            let url = outcome.url.rsplit('/').next().unwrap().to_string();
+            sink.lock()
+                .unwrap()
+                .push((url, hops, fetched.status.as_u16()));
+        })
+        .build()
+        .unwrap();
+
+    let run = warmer.run_blocking([server.url(path)]).unwrap();
+    assert_eq!(run.failed(), 0);
+    (seen, run)
+}
+
+fn entry(url: &str, hops: &[u16], status: u16) -> (String, Vec<u16>, u16) {
+    (url.to_string(), hops.to_vec(), status)
+}
+
+#[test]
+fn test_follow() {
+    let (_runtime, server) = start();
+
+    let (seen, _) = warm(&server, RedirectPolicy::Follow(10), false, "/a");
+    assert_eq!(*seen.lock().unwrap(), [entry("a", &[301, 302], 200)]);
+    assert_eq!(server.hits("/c"), 1);
+
+    let (seen, _) = warm(&server, RedirectPolicy::Follow(1), false, "/a");
+    assert_eq!(*seen.lock().unwrap(), [entry("a", &[301], 302)]);
+    assert_eq!(server.hits("/c"), 1);
+}
+
+#[test]
+fn test_no_follow() {
+    let (_runtime, server) = start();
+
+    let (seen, _) = warm(&server, RedirectPolicy::None, false, "/a");
+    assert_eq!(*seen.lock().unwrap(), [entry("a", &[], 301)]);
+    assert_eq!(server.hits("/b"), 0);
+}
+
+#[test]
+fn test_same_host() {
+    let (_runtime, server) = start();
+
+    let (seen, _) = warm(&server, RedirectPolicy::SameHost(10), false, "/elsewhere");
+    assert_eq!(*seen.lock().unwrap(), [entry("elsewhere", &[], 301)]);
+
+    let (seen, _) = warm(&server, RedirectPolicy::SameHost(10), false, "/a");
+    assert_eq!(*seen.lock().unwrap(), [entry("a", &[301, 302], 200)]);
+}
+
+#[test]
+fn test_warm_targets() {
+    let (_runtime, server) = start();
+
+    let (seen, _) = warm(&server, RedirectPolicy::None, true, "/a");
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            entry("a", &[], 301),
+            entry("b", &[], 302),
+            entry("c", &[], 200),
+        ]
+    );
+    assert_eq!(server.hits("/c"), 1);
+
+    let (seen, run) = warm(&server, RedirectPolicy::Follow(10), true, "/a");
+    assert_eq!(
+        *seen.lock().unwrap(),
+        [
+            entry("a", &[301, 302], 200),
+            entry("b", &[302], 200),
+            entry("c", &[], 200),
+        ]
+    );
+    assert_eq!(server.hits("/c"), 4);
+    // the totals count the targets like the cases do
+    assert_eq!(run.cases.len(), 3);
+    assert_eq!(run.totals().content_length, 300);
+}
@@ -0,0 +1,421 @@
+mod mock;
+
+use cachewarmer::Warmer;
+use cachewarmer::assertions::Assertion;
+use cachewarmer::breaker::BreakerConfig;
+use cachewarmer::checkpoint::Checkpoint;
+use cachewarmer::fetch::{Fetcher, Job, Outcome};
+use cachewarmer::input::{self, JobList, Source};
+use cachewarmer::origin::Origin;
+use cachewarmer::progress::Progress;
+use cachewarmer::proxy::{PoolMode, ProxyPool};
+use cachewarmer::run::Run;
+use cachewarmer::signals::Signals;
+use cachewarmer::strategy::{Executor, Strategy};
+use cachewarmer::tls::CertReport;
+use mock::{Fault, MockServer, Route};
+use reqwest::StatusCode;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+fn routes() -> Vec<(&'static str, Route)> {
+    vec![
+        ("/small", Route::ok().body_size(100)),
+        ("/large", Route::ok().body_size(100_000).chunked()),
+        (
+            "/slow",
+            Route::ok().body_size(10).latency(Duration::from_millis(50)),
+        ),
+        (
+            "/cached",
+            Route::ok().body_size(1000).cache_control("max-age=3600"),
+        ),
+        ("/moved", Route::redirect(301, "/small")),
+        ("/reset", Route::fault(Fault::Reset)),
+        ("/truncated", Route::fault(Fault::Truncate).body_size(1000)),
+    ]
+}
+
+fn jobs(server: &MockServer) -> Vec<Job> {
+    let paths: [(&str, Vec<Assertion>); 8] = [
+        ("/small", vec![]),
+        ("/large", vec![]),
+        ("/slow", vec![]),
+        (
+            "/cached",
+            vec![Assertion::Header(
+                "cache-control".to_string(),
+                Some("max-age=3600".to_string()),
+            )],
+        ),
+        ("/moved", vec![Assertion::Status(StatusCode::OK)]),
+        ("/missing", vec![Assertion::Status(StatusCode::OK)]),
+        ("/reset", vec![]),
+        ("/truncated", vec![]),
+    ];
+
+    paths
+        .into_iter()
+        .enumerate()
+        .map(|(index, (path, assertions))| Job {
+            index,
+            url: server.url(path),
+            assertions,
+            priority: 0.0,
+        })
+        .collect()
+}
+
+#[test]
+fn test_every_strategy() {
+    let runtime = Runtime::new().unwrap();
+    let signals = Signals::install().unwrap();
+    let executor = Executor::new(runtime.handle().clone(), signals, 3);
+
+    for strategy in Strategy::ALL {
+        let server = runtime.block_on(MockServer::start(routes()));
+        let jobs = jobs(&server);
+
+        let pool = ProxyPool::new(&[], PoolMode::Every, || {
+            reqwest::Client::builder().redirect(reqwest::redirect::Policy::none())
+        })
+        .unwrap();
+        let progress = Progress::new();
+        progress.set_total(jobs.len());
+        let certs = Arc::new(Mutex::new(CertReport::default()));
+        let fetcher = Arc::new(Fetcher::new(pool, None, certs, progress.clone()));
+        let run = Arc::new(Mutex::new(Run::new(Checkpoint::new(jobs.len()), None)));
+
+        executor
+            .execute(strategy, fetcher, jobs, run.clone())
+            .unwrap();
+
+        let run = run.lock().unwrap();
+        let failed: Vec<_> = run
+            .cases
+            .iter()
+            .filter(|case| !case.passed())
+            .map(|case| case.url.rsplit('/').next().unwrap())
+            .collect();
+
+        assert_eq!(run.cases.len(), 8, "{}", strategy);
+        assert_eq!(failed.len(), 3, "{}: {:?}", strategy, failed);
+        for path in ["missing", "reset", "truncated"] {
+            assert!(failed.contains(&path), "{}: {:?}", strategy, failed);
+        }
+
+        // the 404 and the followed redirect still count as warmed
+        assert_eq!(
+            run.totals().content_length,
+            100 + 100_000 + 10 + 1000 + 100,
+            "{}",
+            strategy
+        );
+        assert_eq!(run.checkpoint.done_count(), 6, "{}", strategy);
+
+        assert_eq!(server.hits("/small"), 2, "{}", strategy);
+        assert_eq!(server.hits("/moved"), 1, "{}", strategy);
+        assert_eq!(server.total_hits(), 9, "{}", strategy);
+
+        let snapshot = progress.snapshot();
+        assert_eq!(snapshot.completed, 6, "{}", strategy);
+        assert_eq!(snapshot.failed, 2, "{}", strategy);
+        assert_eq!(snapshot.in_flight, 0, "{}", strategy);
+    }
+}
+
+#[test]
+fn test_lazy_jobs() {
+    let runtime = Runtime::new().unwrap();
+    let signals = Signals::install().unwrap();
+    let mut executor = Executor::new(runtime.handle().clone(), signals.clone(), 1);
+    executor.set_concurrency(4);
+    let server = runtime.block_on(MockServer::start(routes()));
+
+    // a million URLs, of which only those warmed before the stop are made
+    let line = format!("{}?n={{1..1000000}}\n", server.url("/small"));
+    let list = JobList::read(line.as_bytes(), None).unwrap();
+    assert_eq!(list.len(), 1_000_000);
+    let taken = Arc::new(AtomicUsize::new(0));
+    let counter = taken.clone();
+    let jobs = list.jobs().inspect(move |_| {
+        counter.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let pool = ProxyPool::new(&[], PoolMode::Every, reqwest::Client::builder).unwrap();
+    let certs = Arc::new(Mutex::new(CertReport::default()));
+    let fetcher = Arc::new(Fetcher::new(pool, None, certs, Progress::new()));
+    let run = Arc::new(Mutex::new(Run::new(Checkpoint::new(0), None)));
+    let stop = signals.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        stop.request_stop();
+    });
+    executor
+        .execute(Strategy::FuturesUnordered, fetcher, jobs, run.clone())
+        .unwrap();
+
+    // the jobs taken are those warmed before the stop, not the whole range
+    let warmed = run.lock().unwrap().cases.len();
+    let taken = taken.load(Ordering::Relaxed);
+    assert!(warmed > 0);
+    assert_eq!(taken, warmed);
+    assert_eq!(server.total_hits(), warmed);
+}
+
+#[test]
+fn test_warmer_blocking() {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start(routes()));
+
+    let warmer = Warmer::builder()
+        .concurrency(2)
+        .retries(2, Duration::from_millis(10))
+        .build()
+        .unwrap();
+    let urls: Vec<_> = ["/small", "/large", "/reset"]
+        .into_iter()
+        .map(|path| server.url(path))
+        .collect();
+
+    let run = warmer.run_blocking(urls).unwrap();
+    assert_eq!(run.failed(), 1);
+    assert_eq!(run.totals().content_length, 100_100);
+    assert_eq!(server.hits("/reset"), 3);
+}
+
+#[tokio::test]
+async fn test_warmer_retries() {
+    let server = MockServer::start([("/flaky", Route::ok().body_size(5).fail_first(1))]).await;
+    let seen = Arc::new(AtomicUsize::new(0));
+
+    let counter = seen.clone();
+    let warmer = Warmer::builder()
+        .concurrency(1)
+        .retries(1, Duration::from_millis(10))
+        .sink(move |_: &Outcome| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })
+        .build()
+        .unwrap();
+
+    let url = server.url("/flaky");
+    let run = warmer.run([url.clone(), url]).await.unwrap();
+    assert_eq!(run.failed(), 0);
+    assert_eq!(run.totals().content_length, 10);
+    assert_eq!(seen.load(Ordering::SeqCst), 2);
+    assert_eq!(server.hits("/flaky"), 3);
+}
+
+#[test]
+fn test_warmer_timeout() {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start([(
+        "/slow",
+        Route::ok().latency(Duration::from_secs(5)),
+    )]));
+
+    let warmer = Warmer::builder()
+        .timeout(Duration::from_millis(100))
+        .build()
+        .unwrap();
+
+    let run = warmer.run_blocking([server.url("/slow")]).unwrap();
+    assert_eq!(run.failed(), 1);
+}
+
+#[test]
+fn test_circuit_breaker() {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start([
+        ("/error", Route::ok().status(503)),
+        ("/small", Route::ok().body_size(100)),
+    ]));
+
+    let warmer = Warmer::builder()
+        .concurrency(1)
+        .circuit_breaker(BreakerConfig {
+            window: 4,
+            cooldown: Duration::from_secs(60),
+            ..BreakerConfig::default()
+        })
+        .build()
+        .unwrap();
+    let urls = (0..10).map(|n| server.url(&format!("/error?{}", n)));
+
+    let run = warmer.run_blocking(urls).unwrap();
+    // the 503s count as warmed, only the skipped URLs failed
+    assert_eq!(run.failed(), 6);
+    assert_eq!(server.total_hits(), 4);
+    let host = &run.breakers.hosts[server.addr().to_string().as_str()];
+    assert_eq!((host.trips, host.skipped, host.open), (1, 6, true));
+}
+
+#[test]
+fn test_origin() {
+    let runtime = Runtime::new().unwrap();
+    let cdn = runtime.block_on(MockServer::start([
+        ("/same", Route::ok().body_size(100).header("etag", "\"1\"")),
+        ("/stale", Route::ok().body_size(100).header("etag", "\"1\"")),
+        ("/short", Route::ok().body_size(50)),
+    ]));
+    let origin = runtime.block_on(MockServer::start([
+        ("/same", Route::ok().body_size(100).header("etag", "\"1\"")),
+        ("/stale", Route::ok().body_size(100).header("etag", "\"2\"")),
+        ("/short", Route::ok().body_size(100)),
+    ]));
+
+    let warmer = Warmer::builder()
+        .origin(origin.url("/").parse().unwrap())
+        .build()
+        .unwrap();
+    let run = warmer
+        .run_blocking(["/same", "/stale", "/short"].map(|path| cdn.url(path)))
+        .unwrap();
+    assert_eq!(run.failed(), 0);
+    assert_eq!(run.origin.compared, 3);
+    assert_eq!(origin.total_hits(), 3);
+    let mut mismatches: Vec<_> = run
+        .origin
+        .mismatches
+        .iter()
+        .map(|mismatch| (mismatch.url.clone(), mismatch.differences.len()))
+        .collect();
+    mismatches.sort();
+    assert_eq!(mismatches, [(cdn.url("/short"), 1), (cdn.url("/stale"), 1)]);
+
+    // an address override keeps the URL and only changes where it connects
+    let url = origin.url("/same").replace("127.0.0.1", "localhost");
+    for (ip, mismatches) in [([127, 0, 0, 1], 0), ([127, 0, 0, 2], 1)] {
+        let warmer = Warmer::builder()
+            .origin(Origin::Address(ip.into()))
+            .build()
+            .unwrap();
+        let run = warmer.run_blocking([url.clone()]).unwrap();
+        assert_eq!(run.failed(), 0);
+        assert_eq!(run.origin.compared, 1);
+        assert_eq!(run.origin.mismatches.len(), mismatches);
+    }
+}
+
+#[test]
+fn test_follow() {
+    let runtime = Runtime::new().unwrap();
+    let signals = Signals::install().unwrap();
+    let executor = Executor::new(runtime.handle().clone(), signals.clone(), 1);
+    let server = runtime.block_on(MockServer::start(routes()));
+
+    let path = std::env::temp_dir().join(format!("cachewarmer-follow-{}", std::process::id()));
+    // an invalid line only fails itself
+    let head = format!(
+        "{}\n{} priority=high\n",
+        server.url("/small"),
+        server.url("/bad")
+    );
+    std::fs::write(&path, head).unwrap();
+    let urls = [server.url("/large"), server.url("/cached")];
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    let writer = std::thread::spawn(move || {
+        for url in urls {
+            std::thread::sleep(Duration::from_millis(150));
+            // a line written in two parts must still come out whole
+            let (start, end) = url.split_at(10);
+            write!(file, "{}", start).unwrap();
+            file.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            writeln!(file, "{}", end).unwrap();
+        }
+    });
+
+    let pool = ProxyPool::new(&[], PoolMode::Every, reqwest::Client::builder).unwrap();
+    let certs = Arc::new(Mutex::new(CertReport::default()));
+    let fetcher = Arc::new(Fetcher::new(pool, None, certs, Progress::new()));
+    let run = Arc::new(Mutex::new(Run::new(Checkpoint::new(0), None)));
+
+    // the file never ends, so stop after the lines written above
+    let jobs = input::jobs(Source::File(path.clone()).follow(&signals).unwrap(), None).take(4);
+    executor.follow(fetcher, jobs, &run).unwrap();
+    writer.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let run = run.lock().unwrap();
+    assert_eq!((run.cases.len(), run.failed()), (4, 1));
+    let invalid = run.cases.iter().find(|case| !case.passed()).unwrap();
+    assert!(invalid.failures[0].contains("Invalid priority"));
+    assert_eq!(run.totals().content_length, 100 + 100_000 + 1000);
+    assert_eq!(server.total_hits(), 3);
+}
+
+#[test]
+fn test_priority() {
+    let runtime = Runtime::new().unwrap();
+    let signals = Signals::install().unwrap();
+    let mut executor = Executor::new(runtime.handle().clone(), signals, 1);
+    executor.set_concurrency(1);
+    let server = runtime.block_on(MockServer::start(routes()));
+
+    for strategy in [
+        Strategy::BlockOnSequential,
+        Strategy::BlockOnWorkerPool,
+        Strategy::FuturesUnordered,
+    ] {
+        let jobs = [
+            ("/small", 0.0),
+            ("/large", 2.0),
+            ("/cached", 0.0),
+            ("/slow", 5.0),
+        ]
+        .map(|(path, priority)| Job {
+            priority,
+            ..Job::from(server.url(path))
+        });
+        let list = JobList::from_jobs(jobs.to_vec());
+
+        let pool = ProxyPool::new(&[], PoolMode::Every, reqwest::Client::builder).unwrap();
+        let certs = Arc::new(Mutex::new(CertReport::default()));
+        let fetcher = Arc::new(Fetcher::new(pool, None, certs, Progress::new()));
+        let run = Arc::new(Mutex::new(Run::new(Checkpoint::new(jobs.len()), None)));
+        executor
+            .execute(strategy, fetcher, list.jobs(), run.clone())
+            .unwrap();
+
+        // highest priority first, ties in list order
+        let order: Vec<_> = run
+            .lock()
+            .unwrap()
+            .cases
+            .iter()
+            .map(|case| case.url.rsplit('/').next().unwrap().to_string())
+            .collect();
+        assert_eq!(order, ["slow", "large", "small", "cached"], "{}", strategy);
+    }
+
+    // the same through the library, keeping each job's place in the list
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let warmer = Warmer::builder()
+        .concurrency(1)
+        .sink(move |outcome: &Outcome| {
+            let path = outcome.url.rsplit('/').next().unwrap().to_string();
+            sink.lock().unwrap().push((path, outcome.index));
+        })
+        .build()
+        .unwrap();
+    let jobs = [("/small", 0.0), ("/large", 2.0), ("/slow", 5.0)].map(|(path, priority)| Job {
+        priority,
+        ..Job::from(server.url(path))
+    });
+    warmer.run_blocking(jobs).unwrap();
+    let seen = seen.lock().unwrap();
+    let seen: Vec<_> = seen
+        .iter()
+        .map(|(path, index)| (path.as_str(), *index))
+        .collect();
+    assert_eq!(seen, [("slow", 2), ("large", 1), ("small", 0)]);
+}
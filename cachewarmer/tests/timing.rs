@@ -0,0 +1,46 @@
+mod mock;
+
+use cachewarmer::Warmer;
+use cachewarmer::fetch::Outcome;
+use cachewarmer::timing::Phases;
+use mock::{MockServer, Route};
+use std::sync::{Arc, Mutex};
+use tokio::runtime::Runtime;
+
+#[test]
+fn test_phases() {
+    let runtime = Runtime::new().unwrap();
+    let server = runtime.block_on(MockServer::start([("/a", Route::ok().body_size(100))]));
+    // by name, so that there is a lookup to time
+    let url = format!("http://localhost:{}/a", server.addr().port());
+
+    let seen: Arc<Mutex<Vec<Phases>>> = Arc::default();
+    let sink = seen.clone();
+    let warmer = Warmer::builder()
+        .concurrency(1)
+        .sink(move |outcome: &Outcome| {
+            let fetched = outcome.routes[0].result.as_ref().unwrap();
+            sink.lock().unwrap().push(fetched.phases);
+        })
+        .build()
+        .unwrap();
+
+    let run = warmer.run_blocking(vec![url; 3]).unwrap();
+    assert_eq!(run.failed(), 0);
+
+    let seen = seen.lock().unwrap();
+    let first = seen[0];
+    assert!(!first.pooled);
+    assert!(first.dns.is_some());
+    assert!(first.connect.is_some());
+    assert_eq!(first.tls, None);
+    assert!(first.ttfb >= first.dns.unwrap() + first.connect.unwrap());
+
+    // the connection may not be back in the pool in time for the second
+    let phases = &run.totals().phases;
+    assert_eq!(phases.requests, 3);
+    assert!(phases.pooled >= 1);
+    for pooled in seen.iter().filter(|phases| phases.pooled) {
+        assert_eq!((pooled.dns, pooled.connect), (None, None));
+    }
+}
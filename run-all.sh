@@ -8,3 +8,5 @@ for i in $(seq 0 11)
 do
 	cargo run --release --bin level"$i" -- urls.txt
 done
+
+cargo run --release --bin cachewarmer -- --strategy all urls.txt